@@ -1,19 +1,16 @@
 use crate::runner::Solve;
+use aoc2022::interval::IntervalSet;
 
 pub struct Day04;
 
-#[derive(Clone, Copy, Debug)]
-struct Range {
-    start: i32,
-    end: i32,
-}
+type Range = IntervalSet<i32>;
 
 fn parse_range(input: &str) -> Range {
     let mut tokens = input.split('-');
     let start = tokens.next().unwrap().parse::<i32>().unwrap();
     let end = tokens.next().unwrap().parse::<i32>().unwrap();
 
-    Range { start, end }
+    Range::from_interval(start, end)
 }
 
 fn parse_line(input: &str) -> (Range, Range) {
@@ -25,13 +22,13 @@ fn parse_input(input: &str) -> Vec<(Range, Range)> {
     input.lines().map(parse_line).collect()
 }
 
-fn fully_contains(start: &Range, end: &Range) -> bool {
-    start.start >= end.start && start.end <= end.end
-        || end.start >= start.start && end.end <= start.end
+fn fully_contains(left: &Range, right: &Range) -> bool {
+    let union = left.union(right);
+    union == *left || union == *right
 }
 
 fn check_overlap(left: &Range, right: &Range) -> bool {
-    !(left.end < right.start || left.start > right.end)
+    !left.intersection(right).is_empty()
 }
 
 impl Solve<usize, usize> for Day04 {
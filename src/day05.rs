@@ -2,7 +2,7 @@ use crate::runner::Solve;
 
 pub struct Day05;
 
-fn parse_stack(board: &Vec<Vec<char>>, col: usize) -> Vec<char> {
+fn parse_stack(board: &[Vec<char>], col: usize) -> Vec<char> {
     let row_start = board.len() - 2;
     (0..=row_start)
         .rev()
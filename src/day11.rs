@@ -1,3 +1,6 @@
+use std::collections::HashMap;
+use std::fmt;
+
 use crate::runner::Solve;
 
 pub struct Day11;
@@ -75,55 +78,263 @@ fn calculate_worry(item: u64, operation: Operation) -> u64 {
     }
 }
 
-/// Constraint: monkeys.len() == inspections.len()
-fn round(monkeys: &mut [Monkey], inspections: &mut [usize], worry_division: u64) {
-    assert!(monkeys.len() == inspections.len());
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Worry can be kept modulo the lcm of every divisor without affecting any of the conditions,
+/// allowing us to keep worry between 0..lcm
+fn worry_modulo(monkeys: &[Monkey]) -> u64 {
+    monkeys
+        .iter()
+        .map(|monkey| monkey.divisible_by)
+        .fold(1, |acc, divisor| acc / gcd(acc, divisor) * divisor)
+}
+
+fn throw_target(monkey: &Monkey, worry: u64) -> usize {
+    if worry.is_multiple_of(monkey.divisible_by) {
+        monkey.throw_true
+    } else {
+        monkey.throw_false
+    }
+}
 
-    // because divisible_by is always a prime number, we can mod worry by the product of these values
-    // without affecting the conditions in any way, but allowing us to keep worry between 0..max
-    let max: u64 = monkeys.iter().map(|monkey| monkey.divisible_by).product();
+/// Records every throw into `throws[from][to]`.
+///
+/// Constraint: monkeys.len() == throws.len()
+fn round(monkeys: &mut [Monkey], throws: &mut [Vec<u64>], worry_division: u64) {
+    assert!(monkeys.len() == throws.len());
+
+    let max = worry_modulo(monkeys);
 
     for monkey_idx in 0..monkeys.len() {
         let items: Vec<u64> = monkeys[monkey_idx].items.drain(..).collect();
         for item in items {
-            inspections[monkey_idx] += 1;
             let worry = calculate_worry(item, monkeys[monkey_idx].operation) / worry_division;
-
-            let throw_to_idx = if worry % monkeys[monkey_idx].divisible_by == 0 {
-                monkeys[monkey_idx].throw_true
-            } else {
-                monkeys[monkey_idx].throw_false
-            };
+            let throw_to_idx = throw_target(&monkeys[monkey_idx], worry);
+            throws[monkey_idx][throw_to_idx] += 1;
 
             monkeys[throw_to_idx].items.push(worry % max);
         }
     }
 }
-fn monkey_business(mut inspections: Vec<usize>) -> usize {
-    inspections.sort();
 
-    inspections.into_iter().rev().take(2).product()
+/// Follows a single item through one round, starting at monkey `at` with the given worry.
+///
+/// Monkeys take turns in order, so an item thrown to a later monkey is inspected again in the
+/// same round, while one thrown to an earlier (or the same) monkey waits for the next round.
+fn item_round(
+    monkeys: &[Monkey],
+    (mut at, mut worry): (usize, u64),
+    max: u64,
+    throws: &mut [Vec<u64>],
+) -> (usize, u64) {
+    loop {
+        worry = calculate_worry(worry, monkeys[at].operation) % max;
+        let next = throw_target(&monkeys[at], worry);
+        throws[at][next] += 1;
+
+        if next <= at {
+            return (next, worry);
+        }
+        at = next;
+    }
 }
 
-impl Solve<usize, usize> for Day11 {
-    fn part1(input: &str) -> usize {
-        let mut monkeys: Vec<Monkey> = input.split("\n\n").map(parse_monkey).collect();
-        let mut inspections = vec![0; monkeys.len()];
+/// Throws caused by a single item over `rounds` rounds without worry relief.
+///
+/// Items never interact with each other, and the (monkey, worry % lcm) state of an item can only
+/// take finitely many values, so its trajectory is eventually periodic. Once a state repeats, the
+/// throws done during one period are extrapolated over the remaining rounds. `None` when a count
+/// doesn't fit in 64 bits.
+fn item_throws(
+    monkeys: &[Monkey],
+    start: (usize, u64),
+    rounds: u64,
+    max: u64,
+) -> Option<Vec<Vec<u64>>> {
+    let start = (start.0, start.1 % max);
+    let mut throws = vec![vec![0; monkeys.len()]; monkeys.len()];
+
+    // the round each state was first reached in, to find where the trajectory starts repeating
+    let mut seen: HashMap<(usize, u64), u64> = HashMap::new();
+    let mut state = start;
+    let mut cycle = None;
+    for current in 0..rounds {
+        if let Some(cycle_start) = seen.insert(state, current) {
+            cycle = Some((cycle_start, current - cycle_start));
+            break;
+        }
+        state = item_round(monkeys, state, max, &mut throws);
+    }
+    let Some((cycle_start, cycle_length)) = cycle else {
+        return Some(throws);
+    };
+
+    let remaining = rounds - cycle_start;
+    let (cycle_count, leftover) = (remaining / cycle_length, remaining % cycle_length);
+
+    // replay up to the end of the first period, keeping the totals at its start and at the
+    // point the last partial period stops
+    let mut throws = vec![vec![0; monkeys.len()]; monkeys.len()];
+    let (mut at_start, mut at_leftover) = (Vec::new(), Vec::new());
+    let mut state = start;
+    for current in 0..=cycle_start + cycle_length {
+        if current == cycle_start {
+            at_start = throws.clone();
+        }
+        if current == cycle_start + leftover {
+            at_leftover = throws.clone();
+        }
+        if current < cycle_start + cycle_length {
+            state = item_round(monkeys, state, max, &mut throws);
+        }
+    }
+
+    at_leftover
+        .iter()
+        .zip(at_start.iter().zip(&throws))
+        .map(|(partial, (start_row, end_row))| {
+            partial
+                .iter()
+                .zip(start_row.iter().zip(end_row))
+                .map(|(count, (from, to))| cycle_count.checked_mul(to - from)?.checked_add(*count))
+                .collect()
+        })
+        .collect()
+}
 
-        for _ in 0..20 {
-            round(&mut monkeys, &mut inspections, 3);
+/// Throws done by every item over `rounds` rounds without worry relief, `None` when a count
+/// doesn't fit in 64 bits.
+fn simulate_throws(monkeys: &[Monkey], rounds: u64) -> Option<Vec<Vec<u64>>> {
+    let max = worry_modulo(monkeys);
+    let mut throws: Vec<Vec<u64>> = vec![vec![0; monkeys.len()]; monkeys.len()];
+
+    for (monkey_idx, monkey) in monkeys.iter().enumerate() {
+        for &item in monkey.items.iter() {
+            let item_total = item_throws(monkeys, (monkey_idx, item), rounds, max)?;
+            for (row, item_row) in throws.iter_mut().zip(item_total) {
+                for (total, count) in row.iter_mut().zip(item_row) {
+                    *total = total.checked_add(count)?;
+                }
+            }
         }
+    }
 
-        monkey_business(inspections)
+    Some(throws)
+}
+
+/// Every throw is preceded by an inspection, `None` when a count doesn't fit in 64 bits
+fn inspections(throws: &[Vec<u64>]) -> Option<Vec<u64>> {
+    throws
+        .iter()
+        .map(|row| {
+            row.iter()
+                .try_fold(0u64, |total, &count| total.checked_add(count))
+        })
+        .collect()
+}
+
+fn too_many_rounds(rounds: u64) -> String {
+    format!("throw counts after {} rounds don't fit in 64 bits", rounds)
+}
+
+fn monkey_business(mut inspections: Vec<u64>) -> u128 {
+    inspections.sort();
+
+    inspections
+        .into_iter()
+        .rev()
+        .take(2)
+        .map(u128::from)
+        .product()
+}
+
+fn parse_input(input: &str) -> Vec<Monkey> {
+    input.split("\n\n").map(parse_monkey).collect()
+}
+
+/// Monkey business after `rounds` rounds with worry relief, as in part 1. Every round is
+/// simulated.
+pub fn monkey_business_with_relief(input: &str, rounds: u64) -> u128 {
+    let mut monkeys = parse_input(input);
+    let mut throws = vec![vec![0; monkeys.len()]; monkeys.len()];
+
+    for _ in 0..rounds {
+        round(&mut monkeys, &mut throws, 3);
     }
-    fn part2(input: &str) -> usize {
-        let mut monkeys: Vec<Monkey> = input.split("\n\n").map(parse_monkey).collect();
-        let mut inspections = vec![0; monkeys.len()];
 
-        for _ in 0..10_000 {
-            round(&mut monkeys, &mut inspections, 1);
+    monkey_business(inspections(&throws).unwrap())
+}
+
+/// Monkey business after any amount of rounds without worry relief, as in part 2
+pub fn monkey_business_after(input: &str, rounds: u64) -> Result<u128, String> {
+    let monkeys = parse_input(input);
+    let inspections = simulate_throws(&monkeys, rounds)
+        .as_deref()
+        .and_then(inspections)
+        .ok_or_else(|| too_many_rounds(rounds))?;
+
+    Ok(monkey_business(inspections))
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::MultiplySelf => write!(f, "old * old"),
+            Operation::Plus(v) => write!(f, "old + {}", v),
+            Operation::Multiply(v) => write!(f, "old * {}", v),
         }
-        monkey_business(inspections)
+    }
+}
+
+/// Graphviz DOT of the throw graph, with each edge labelled by how many items went through it
+/// during `rounds` rounds without worry relief. Busier edges are drawn thicker.
+pub fn dot(input: &str, rounds: u64) -> Result<String, String> {
+    let monkeys = parse_input(input);
+    let throws = simulate_throws(&monkeys, rounds).ok_or_else(|| too_many_rounds(rounds))?;
+    let inspections = inspections(&throws).ok_or_else(|| too_many_rounds(rounds))?;
+    let busiest = throws.iter().flatten().copied().max().unwrap_or(0).max(1);
+
+    let mut result = String::from("digraph monkeys {\n");
+    for (idx, monkey) in monkeys.iter().enumerate() {
+        result += &format!(
+            "    {} [label=\"Monkey {}\\nnew = {}\\ndivisible by {}\\ninspected {}\"];\n",
+            idx, idx, monkey.operation, monkey.divisible_by, inspections[idx]
+        );
+    }
+    for (idx, monkey) in monkeys.iter().enumerate() {
+        // throws are counted per target, so a monkey throwing to the same target either way
+        // only gets a single edge
+        let edges = if monkey.throw_true == monkey.throw_false {
+            vec![("always", monkey.throw_true)]
+        } else {
+            vec![("true", monkey.throw_true), ("false", monkey.throw_false)]
+        };
+        for (condition, to) in edges {
+            let count = throws[idx][to];
+            let width = 1.0 + 4.0 * count as f64 / busiest as f64;
+            result += &format!(
+                "    {} -> {} [label=\"{}: {}\", penwidth={:.2}];\n",
+                idx, to, condition, count, width
+            );
+        }
+    }
+    result += "}\n";
+
+    Ok(result)
+}
+
+impl Solve<u128, u128> for Day11 {
+    fn part1(input: &str) -> u128 {
+        monkey_business_with_relief(input, 20)
+    }
+    fn part2(input: &str) -> u128 {
+        monkey_business_after(input, 10_000).unwrap()
     }
 }
 
@@ -175,6 +386,14 @@ Monkey 3:
         );
     }
 
+    #[test]
+    fn relief_after_any_rounds() {
+        // after the first round monkeys 1 and 3 inspected 4 and 5 items
+        assert_eq!(monkey_business_with_relief(EXAMPLE, 0), 0);
+        assert_eq!(monkey_business_with_relief(EXAMPLE, 1), 20);
+        assert_eq!(monkey_business_with_relief(EXAMPLE, 20), 10605);
+    }
+
     #[test]
     fn example_p1() {
         let result = Day11::part1(EXAMPLE);
@@ -190,4 +409,72 @@ Monkey 3:
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn cycle_detection_matches_simulation() {
+        for rounds in [1, 7, 20, 1_234] {
+            let mut monkeys = parse_input(EXAMPLE);
+            let mut throws = vec![vec![0; monkeys.len()]; monkeys.len()];
+            for _ in 0..rounds {
+                round(&mut monkeys, &mut throws, 1);
+            }
+
+            assert_eq!(simulate_throws(&parse_input(EXAMPLE), rounds), Some(throws));
+        }
+    }
+
+    #[test]
+    fn extrapolates_huge_round_counts() {
+        // every item in the example is on its cycle after 175 rounds, and all cycle lengths
+        // divide this period, so any period late enough adds the same throws
+        let period = 76_608;
+        let difference = |later: Vec<Vec<u64>>, earlier: Vec<Vec<u64>>| -> Vec<u64> {
+            later
+                .iter()
+                .flatten()
+                .zip(earlier.iter().flatten())
+                .map(|(later, earlier)| later - earlier)
+                .collect()
+        };
+        let simulated = |rounds| {
+            let mut monkeys = parse_input(EXAMPLE);
+            let mut throws = vec![vec![0; monkeys.len()]; monkeys.len()];
+            for _ in 0..rounds {
+                round(&mut monkeys, &mut throws, 1);
+            }
+            throws
+        };
+        let extrapolated = |rounds| simulate_throws(&parse_input(EXAMPLE), rounds).unwrap();
+
+        let rounds = 1_000_000_000_000;
+        assert_eq!(
+            difference(extrapolated(rounds), extrapolated(rounds - period)),
+            difference(simulated(200 + period), simulated(200))
+        );
+        assert_eq!(
+            monkey_business_after(EXAMPLE, rounds),
+            Ok(5_217_653_508_757 * 5_202_028_508_760)
+        );
+        assert!(monkey_business_after(EXAMPLE, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn dot_example() {
+        let result = dot(EXAMPLE, 20).unwrap();
+
+        assert!(result.starts_with("digraph monkeys {\n"));
+        assert!(result.contains("2 [label=\"Monkey 2\\nnew = old * old\\ndivisible by 13"));
+        assert_eq!(result.matches(" -> ").count(), 8);
+        assert!(result.ends_with("}\n"));
+    }
+
+    #[test]
+    fn dot_merges_same_target() {
+        let input = EXAMPLE.replace("If false: throw to monkey 3", "If false: throw to monkey 2");
+        let result = dot(&input, 20).unwrap();
+        let throws = simulate_throws(&parse_input(&input), 20).unwrap();
+
+        assert_eq!(result.matches(" -> ").count(), 7);
+        assert!(result.contains(&format!("0 -> 2 [label=\"always: {}\"", throws[0][2])));
+    }
 }
@@ -1,56 +1,201 @@
 use std::cmp::Ordering;
+use std::fmt;
+use std::slice;
 
+use crate::json::{self, Json, JsonError};
 use crate::runner::Solve;
 
 pub struct Day13;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 enum Value {
-    Integer(i32),
+    Integer(i64),
     List(Vec<Value>),
 }
 
-fn parse_value(input: &str) -> (&str, Value) {
-    if let Some(mut input) = input.strip_prefix('[') {
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ParseErrorKind {
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    IntegerOverflow,
+}
+
+/// Packet parse failure, `offset` is the byte offset into the packet where it happened
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct ParseError {
+    offset: usize,
+    kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of packet"),
+            ParseErrorKind::UnexpectedChar(ch) => write!(f, "unexpected {:?}", ch),
+            ParseErrorKind::IntegerOverflow => write!(f, "integer doesn't fit in 64 bits"),
+        }?;
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+/// Recursive descent parser for the packet grammar:
+///
+/// ```text
+/// value   = integer | list
+/// list    = "[" [ value { "," value } ] "]"
+/// integer = [ "-" ] digit { digit }
+/// ```
+///
+/// Whitespace is allowed between any two tokens.
+struct Parser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Parser { input, offset: 0 }
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.offset..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.offset..];
+        self.offset += rest.len() - rest.trim_start().len();
+    }
+
+    /// Skips whitespace and returns the next char without consuming it
+    fn next_token(&mut self) -> Result<char, ParseError> {
+        self.skip_whitespace();
+        self.peek()
+            .ok_or_else(|| self.error(ParseErrorKind::UnexpectedEnd))
+    }
+
+    fn unexpected(&self, ch: char) -> ParseError {
+        self.error(ParseErrorKind::UnexpectedChar(ch))
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.next_token()? {
+            '[' => self.list(),
+            '-' | '0'..='9' => self.integer(),
+            ch => Err(self.unexpected(ch)),
+        }
+    }
+
+    fn list(&mut self) -> Result<Value, ParseError> {
+        // skip the opening bracket
+        self.offset += 1;
         let mut result = Vec::new();
-        while &input[0..1] != "]" {
-            if &input[0..1] == "," {
-                input = &input[1..];
+
+        if self.next_token()? == ']' {
+            self.offset += 1;
+            return Ok(Value::List(result));
+        }
+
+        loop {
+            result.push(self.value()?);
+
+            match self.next_token()? {
+                ',' => self.offset += 1,
+                ']' => {
+                    self.offset += 1;
+                    return Ok(Value::List(result));
+                }
+                ch => return Err(self.unexpected(ch)),
             }
+        }
+    }
 
-            let (rest, value) = parse_value(input);
-            input = rest;
-            result.push(value);
+    fn integer(&mut self) -> Result<Value, ParseError> {
+        let start = self.offset;
+        if self.peek() == Some('-') {
+            self.offset += 1;
         }
 
-        (&input[1..], Value::List(result))
-    } else {
-        let n = input[0..2]
-            .chars()
-            .take_while(|ch| char::is_numeric(*ch))
+        let digits = self.input[self.offset..]
+            .bytes()
+            .take_while(u8::is_ascii_digit)
             .count();
+        if digits == 0 {
+            return match self.peek() {
+                Some(ch) => Err(self.unexpected(ch)),
+                None => Err(self.error(ParseErrorKind::UnexpectedEnd)),
+            };
+        }
+        self.offset += digits;
 
-        let number = input[0..n].parse().unwrap();
-        (&input[n..], Value::Integer(number))
+        self.input[start..self.offset]
+            .parse()
+            .map(Value::Integer)
+            .map_err(|_| ParseError {
+                offset: start,
+                kind: ParseErrorKind::IntegerOverflow,
+            })
     }
 }
 
-fn parse_input(input: &str) -> Vec<Value> {
+/// Parses a single packet, which must span the whole input apart from surrounding whitespace.
+fn parse_packet(input: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser::new(input);
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+    match parser.peek() {
+        Some(ch) => Err(parser.unexpected(ch)),
+        None => Ok(value),
+    }
+}
+
+/// Parses one packet per line, skipping blank lines. Errors name the line they happened on.
+fn parse_input(input: &str) -> Result<Vec<Value>, String> {
     input
-        .split("\n\n")
-        .flat_map(str::lines)
-        .map(parse_value)
-        .map(|tuple| tuple.1)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| {
+            parse_packet(line)
+                .map_err(|e| format!("failed to parse packet on line {}: {}", idx + 1, e))
+        })
         .collect()
 }
 
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
 impl Ord for Value {
     fn cmp(&self, other: &Self) -> Ordering {
+        // an integer compared against a list behaves as a list holding only that integer,
+        // borrowing it as a one element slice avoids allocating that list
         match (self, other) {
             (Value::Integer(v1), Value::Integer(v2)) => v1.cmp(v2),
             (Value::List(l1), Value::List(l2)) => l1.cmp(l2),
-            (Value::List(l1), value2) => l1.cmp(&vec![value2.clone()]),
-            (value1, Value::List(l2)) => vec![value1.clone()].cmp(l2),
+            (Value::List(l1), value2) => l1.as_slice().cmp(slice::from_ref(value2)),
+            (value1, Value::List(l2)) => slice::from_ref(value1).cmp(l2.as_slice()),
         }
     }
 }
@@ -61,24 +206,142 @@ impl PartialOrd for Value {
     }
 }
 
+impl From<&Value> for Json {
+    fn from(value: &Value) -> Self {
+        match value {
+            Value::Integer(value) => Json::Integer(*value),
+            Value::List(values) => Json::Array(values.iter().map(Json::from).collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum JsonPacketError {
+    Syntax(JsonError),
+    /// Packets can only hold integers and arrays
+    NotAPacket(Json),
+    /// Pair files hold an array of two packet arrays, index of the offending pair
+    NotAPair(usize),
+}
+
+impl fmt::Display for JsonPacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonPacketError::Syntax(e) => write!(f, "invalid json: {}", e),
+            JsonPacketError::NotAPacket(json) => write!(f, "{} is not a packet", json),
+            JsonPacketError::NotAPair(idx) => write!(f, "pair {} is not two packets", idx + 1),
+        }
+    }
+}
+
+impl TryFrom<&Json> for Value {
+    type Error = JsonPacketError;
+
+    fn try_from(json: &Json) -> Result<Self, Self::Error> {
+        match json {
+            Json::Integer(value) => Ok(Value::Integer(*value)),
+            Json::Array(values) => values
+                .iter()
+                .map(Value::try_from)
+                .collect::<Result<_, _>>()
+                .map(Value::List),
+            _ => Err(JsonPacketError::NotAPacket(json.clone())),
+        }
+    }
+}
+
+fn json_array(input: &str) -> Result<Vec<Json>, JsonPacketError> {
+    match json::parse(input).map_err(JsonPacketError::Syntax)? {
+        Json::Array(values) => Ok(values),
+        other => Err(JsonPacketError::NotAPacket(other)),
+    }
+}
+
+/// Reads a JSON array of packets
+fn packets_from_json(input: &str) -> Result<Vec<Value>, JsonPacketError> {
+    json_array(input)?.iter().map(Value::try_from).collect()
+}
+
+/// Reads a JSON array of `[left, right]` packet pairs
+fn pairs_from_json(input: &str) -> Result<Vec<(Value, Value)>, JsonPacketError> {
+    json_array(input)?
+        .iter()
+        .enumerate()
+        .map(|(idx, pair)| match pair {
+            Json::Array(pair) if pair.len() == 2 => {
+                Ok((Value::try_from(&pair[0])?, Value::try_from(&pair[1])?))
+            }
+            _ => Err(JsonPacketError::NotAPair(idx)),
+        })
+        .collect()
+}
+
+/// Sum of the 1-based indices of pairs that are in the right order
+fn ordered_pairs_sum<'a>(pairs: impl Iterator<Item = (&'a Value, &'a Value)>) -> usize {
+    pairs
+        .enumerate()
+        .filter(|(_, (left, right))| left.cmp(right) == Ordering::Less)
+        .map(|(idx, _)| idx + 1)
+        .sum()
+}
+
+/// Sorts packets from either a JSON array or the puzzle's one packet per line format, printing
+/// them back one per line in canonical form.
+pub fn sort_packets(input: &str, is_json: bool) -> Result<String, String> {
+    let mut packets = if is_json {
+        packets_from_json(input).map_err(|e| e.to_string())?
+    } else {
+        parse_input(input)?
+    };
+    packets.sort();
+
+    Ok(packets
+        .iter()
+        .map(|packet| format!("{}\n", packet))
+        .collect())
+}
+
+/// Converts the puzzle input into a JSON array of packet pairs.
+pub fn to_json(input: &str) -> Result<String, String> {
+    let packets = parse_input(input)?;
+    if packets.len() % 2 != 0 {
+        return Err(format!(
+            "{} packets can't be split into pairs, the last one has no partner",
+            packets.len()
+        ));
+    }
+    let pairs = packets
+        .chunks_exact(2)
+        .map(|pair| Json::Array(pair.iter().map(Json::from).collect()))
+        .collect();
+
+    Ok(format!("{}\n", Json::Array(pairs)))
+}
+
+/// Part 1 on a JSON array of packet pairs.
+pub fn check_json_pairs(input: &str) -> Result<String, String> {
+    let pairs = pairs_from_json(input).map_err(|e| e.to_string())?;
+
+    Ok(format!(
+        "{}\n",
+        ordered_pairs_sum(pairs.iter().map(|(left, right)| (left, right)))
+    ))
+}
+
 impl Solve<usize, usize> for Day13 {
     fn part1(input: &str) -> usize {
-        let values = parse_input(input);
+        let values = parse_input(input).unwrap();
 
-        values
-            .chunks_exact(2)
-            .enumerate()
-            .filter(|(_, pair)| pair[0].cmp(&pair[1]) == Ordering::Less)
-            .map(|(idx, _)| idx + 1)
-            .sum()
+        ordered_pairs_sum(values.chunks_exact(2).map(|pair| (&pair[0], &pair[1])))
     }
 
     fn part2(input: &str) -> usize {
-        let mut values = parse_input(input);
+        let mut values = parse_input(input).unwrap();
         let dividers = parse_input(
             r#"[[2]]
 [[6]]"#,
-        );
+        )
+        .unwrap();
         values.extend(dividers.clone());
 
         values.sort();
@@ -129,7 +392,7 @@ mod tests {
             Value::Integer(4),
         ]);
 
-        assert_eq!(expected, parse_value(input).1);
+        assert_eq!(Ok(expected), parse_packet(input));
     }
 
     #[test]
@@ -147,4 +410,176 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn parses_wide_and_negative_integers() {
+        let input = " [ 123456789012 , -7,[ ] ]";
+
+        let expected = Value::List(vec![
+            Value::Integer(123456789012),
+            Value::Integer(-7),
+            Value::List(vec![]),
+        ]);
+
+        assert_eq!(Ok(expected), parse_packet(input));
+    }
+
+    #[test]
+    fn reports_error_offsets() {
+        let unexpected = |offset, ch| ParseError {
+            offset,
+            kind: ParseErrorKind::UnexpectedChar(ch),
+        };
+
+        assert_eq!(
+            parse_packet("[1,2"),
+            Err(ParseError {
+                offset: 4,
+                kind: ParseErrorKind::UnexpectedEnd,
+            })
+        );
+        assert_eq!(parse_packet("[1,,2]"), Err(unexpected(3, ',')));
+        assert_eq!(parse_packet("[1 2]"), Err(unexpected(3, '2')));
+        assert_eq!(parse_packet("[1]]"), Err(unexpected(3, ']')));
+        assert_eq!(parse_packet("[-]"), Err(unexpected(2, ']')));
+        assert_eq!(
+            parse_packet("[99999999999999999999]"),
+            Err(ParseError {
+                offset: 1,
+                kind: ParseErrorKind::IntegerOverflow,
+            })
+        );
+    }
+
+    #[test]
+    fn displays_canonical_form() {
+        for line in EXAMPLE.lines().filter(|line| !line.is_empty()) {
+            assert_eq!(parse_packet(line).unwrap().to_string(), line);
+        }
+
+        assert_eq!(parse_packet(" [ 1 , [ ] ] ").unwrap().to_string(), "[1,[]]");
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let json = to_json(EXAMPLE).unwrap();
+        let pairs = pairs_from_json(&json).unwrap();
+        let packets = parse_input(EXAMPLE).unwrap();
+
+        assert_eq!(pairs.len(), 8);
+        assert_eq!(pairs[1], (packets[2].clone(), packets[3].clone()));
+        assert_eq!(check_json_pairs(&json), Ok(String::from("13\n")));
+    }
+
+    #[test]
+    fn rejects_non_packet_json() {
+        assert_eq!(
+            packets_from_json(r#"[[1], [true]]"#),
+            Err(JsonPacketError::NotAPacket(Json::Bool(true)))
+        );
+        assert_eq!(
+            pairs_from_json("[[[1], [2]], [[3]]]"),
+            Err(JsonPacketError::NotAPair(1))
+        );
+    }
+
+    #[test]
+    fn sorts_json_packets() {
+        let result = sort_packets("[[2], 1, [[]], [], [1, 1]]", true);
+
+        assert_eq!(result, Ok(String::from("[]\n[[]]\n1\n[1,1]\n[2]\n")));
+    }
+
+    #[test]
+    fn reports_bad_packet_lines() {
+        let error = Err(String::from(
+            "failed to parse packet on line 3: unexpected ',' at byte 3",
+        ));
+
+        assert_eq!(sort_packets("[1]\n\n[1,,2]\n", false), error);
+        assert_eq!(to_json("[1]\n\n[1,,2]\n"), error);
+    }
+
+    #[test]
+    fn rejects_unpaired_packet() {
+        assert_eq!(
+            to_json("[1]\n[2]\n\n[3]\n"),
+            Err(String::from(
+                "3 packets can't be split into pairs, the last one has no partner"
+            ))
+        );
+        assert_eq!(to_json(""), Ok(String::from("[]\n")));
+    }
+
+    /// The previous comparison, which wrapped integers in a new Vec
+    fn allocating_cmp(value1: &Value, value2: &Value) -> Ordering {
+        match (value1, value2) {
+            (Value::Integer(v1), Value::Integer(v2)) => v1.cmp(v2),
+            (Value::List(l1), Value::List(l2)) => l1
+                .iter()
+                .zip(l2)
+                .map(|(v1, v2)| allocating_cmp(v1, v2))
+                .find(|&ordering| ordering != Ordering::Equal)
+                .unwrap_or_else(|| l1.len().cmp(&l2.len())),
+            (Value::List(_), value2) => allocating_cmp(value1, &Value::List(vec![value2.clone()])),
+            (value1, Value::List(_)) => allocating_cmp(&Value::List(vec![value1.clone()]), value2),
+        }
+    }
+
+    /// Deterministic pseudo random packets, mixing integers and lists so that comparisons often
+    /// have to promote an integer to a list
+    fn generate_packets(amount: usize) -> Vec<Value> {
+        let mut state: u64 = 0x2545F4914F6CDD1D;
+        let mut next = move |bound: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % bound
+        };
+
+        fn packet(next: &mut impl FnMut(u64) -> u64, depth: usize) -> Value {
+            if depth > 4 || next(3) == 0 {
+                Value::Integer(next(11) as i64)
+            } else {
+                Value::List((0..next(5)).map(|_| packet(next, depth + 1)).collect())
+            }
+        }
+
+        (0..amount)
+            .map(|_| Value::List((0..1 + next(5)).map(|_| packet(&mut next, 1)).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn matches_allocating_cmp() {
+        let packets = generate_packets(300);
+
+        for left in packets.iter() {
+            for right in packets.iter() {
+                assert_eq!(left.cmp(right), allocating_cmp(left, right));
+            }
+        }
+    }
+
+    /// Run with `cargo test --release -- --ignored --nocapture bench_sort`
+    #[test]
+    #[ignore]
+    fn bench_sort() {
+        use std::time::Instant;
+
+        let packets = generate_packets(200_000);
+
+        let mut sorted = packets.clone();
+        let start = Instant::now();
+        sorted.sort();
+        let borrowing = start.elapsed();
+
+        let mut sorted_allocating = packets;
+        let start = Instant::now();
+        sorted_allocating.sort_by(allocating_cmp);
+        let allocating = start.elapsed();
+
+        assert_eq!(sorted, sorted_allocating);
+        println!("borrowing: {:?}, allocating: {:?}", borrowing, allocating);
+    }
 }
@@ -1,4 +1,7 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
 
 use crate::runner::Solve;
 
@@ -38,94 +41,312 @@ fn parse_line(input: &str) -> HashSet<Coordinate> {
 }
 
 fn parse_input(input: &str) -> HashSet<Coordinate> {
-    input.lines().flat_map(parse_line).collect()
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .flat_map(parse_line)
+        .collect()
 }
 
-fn is_solid_without_ground(map: &HashMap<Coordinate, Tile>, at: &Coordinate, _max: i32) -> bool {
-    map.get(at).is_some()
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Tile {
+    Air,
+    Rock,
+    Sand,
 }
 
-fn is_solid_with_ground(map: &HashMap<Coordinate, Tile>, at: &Coordinate, max: i32) -> bool {
-    map.get(at).is_some() || at.1 == max + 2
+/// Dense grid covering every tile sand can reach, anything outside of it is the abyss
+#[derive(Clone, Debug)]
+struct Cave {
+    min_x: i32,
+    min_y: i32,
+    width: usize,
+    height: usize,
+    tiles: Vec<Tile>,
+    /// y of the infinite floor, if the cave has one
+    floor: Option<i32>,
 }
 
-fn produce(
-    map: &HashMap<Coordinate, Tile>,
-    at: Coordinate,
-    max: i32,
-    is_solid: impl Fn(&HashMap<Coordinate, Tile>, &Coordinate, i32) -> bool,
-) -> Option<Coordinate> {
-    let mut current = at;
-
-    while !is_solid(map, &current, max) && current.1 <= max + 1 {
-        let below = (current.0, current.1 + 1);
+impl Cave {
+    /// Builds the cave around `rocks` and `sources`. With a floor `floor_depth` below the lowest
+    /// rock the grid is widened so sand can pile all the way up to each source.
+    fn new(rocks: &HashSet<Coordinate>, sources: &[Coordinate], floor_depth: Option<i32>) -> Cave {
+        let points = || rocks.iter().chain(sources);
+        let max_y = rocks.iter().map(|&(_, y)| y).max().unwrap_or(0);
+        let floor = floor_depth.map(|depth| max_y + depth);
+
+        // a cave without rocks or sources still gets a grid around the origin
+        let min_y = points().map(|&(_, y)| y).min().unwrap_or(0);
+        let mut min_x = points().map(|&(x, _)| x).min().unwrap_or(0) - 1;
+        let mut max_x = points().map(|&(x, _)| x).max().unwrap_or(0) + 1;
+        if let Some(floor) = floor {
+            // sand fans out at most one column per row it falls
+            for &(x, y) in sources {
+                let spread = (floor - y).max(0);
+                min_x = min_x.min(x - spread);
+                max_x = max_x.max(x + spread);
+            }
+        }
+        let bottom = floor.map_or(max_y, |floor| floor - 1);
+
+        let width = (max_x - min_x + 1) as usize;
+        let height = (bottom - min_y + 1).max(0) as usize;
+        let mut cave = Cave {
+            min_x,
+            min_y,
+            width,
+            height,
+            tiles: vec![Tile::Air; width * height],
+            floor,
+        };
+        for &rock in rocks {
+            cave.set(rock, Tile::Rock);
+        }
 
-        if is_solid(map, &below, max) {
-            let left = (below.0 - 1, below.1);
-            let right = (below.0 + 1, below.1);
+        cave
+    }
 
-            if !is_solid(map, &left, max) {
-                current = left;
-            } else if !is_solid(map, &right, max) {
-                current = right;
-            } else {
-                return Some(current);
-            }
+    fn index(&self, (x, y): Coordinate) -> Option<usize> {
+        let col = x - self.min_x;
+        let row = y - self.min_y;
+        if (0..self.width as i32).contains(&col) && (0..self.height as i32).contains(&row) {
+            Some(row as usize * self.width + col as usize)
         } else {
-            current = below;
+            None
+        }
+    }
+
+    /// Tile at `at`, None if it's in the abyss
+    fn get(&self, at: Coordinate) -> Option<Tile> {
+        if Some(at.1) == self.floor {
+            return Some(Tile::Rock);
+        }
+        self.index(at).map(|idx| self.tiles[idx])
+    }
+
+    fn set(&mut self, at: Coordinate, tile: Tile) {
+        if let Some(idx) = self.index(at) {
+            self.tiles[idx] = tile;
         }
     }
-    None
+
+    fn is_free(&self, at: Coordinate) -> bool {
+        matches!(self.get(at), None | Some(Tile::Air))
+    }
 }
 
-#[derive(Clone, Copy, Eq, PartialEq)]
-enum Tile {
-    Rock,
-    Sand,
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Grain {
+    Rested(Coordinate),
+    FellIntoAbyss,
+    SourceBlocked,
 }
 
-impl Solve<usize, usize> for Day14 {
-    fn part1(input: &str) -> usize {
-        let mut tiles: HashMap<Coordinate, Tile> = parse_input(input)
+/// Drops one grain along `path`, the stack of positions the previous grain from the same source
+/// fell through.
+///
+/// Tiles only ever fill up, so a grain follows the previous one's path for as long as that path
+/// is free. A grain can only rest on top of the path, so filled positions are always at the top of
+/// the stack and the next grain resumes from the last free one instead of the source.
+fn drop_grain(cave: &mut Cave, path: &mut Vec<Coordinate>) -> Grain {
+    while let Some(&current) = path.last() {
+        if !cave.is_free(current) {
+            path.pop();
+            continue;
+        }
+
+        let (x, y) = current;
+        match [(x, y + 1), (x - 1, y + 1), (x + 1, y + 1)]
             .into_iter()
-            .map(|coord| (coord, Tile::Rock))
+            .find(|&next| cave.is_free(next))
+        {
+            Some(next) if cave.get(next).is_none() => return Grain::FellIntoAbyss,
+            Some(next) => path.push(next),
+            None => {
+                cave.set(current, Tile::Sand);
+                path.pop();
+                return Grain::Rested(current);
+            }
+        }
+    }
+
+    Grain::SourceBlocked
+}
+
+/// Pours sand from every source in turn, one grain each, until every source either blocked
+/// itself or lost a grain to the abyss. Returns how many grains came to rest.
+///
+/// `on_grain` is called with the cave and the running count after every grain that rests.
+fn simulate(
+    cave: &mut Cave,
+    sources: &[Coordinate],
+    mut on_grain: impl FnMut(&Cave, usize),
+) -> usize {
+    let mut paths: Vec<Vec<Coordinate>> = sources.iter().map(|&source| vec![source]).collect();
+    let mut rested = 0;
+
+    while !paths.is_empty() {
+        paths.retain_mut(|path| match drop_grain(cave, path) {
+            Grain::Rested(_) => {
+                rested += 1;
+                on_grain(cave, rested);
+                true
+            }
+            Grain::FellIntoAbyss | Grain::SourceBlocked => false,
+        });
+    }
+
+    rested
+}
+
+/// Counts the sand in a floored cave once every source is blocked, without dropping any grains.
+///
+/// With a floor nothing falls into the abyss, so the sand ends up filling every tile it can reach:
+/// a tile gets sand when it isn't rock and one of the three tiles above it got sand (or it's a
+/// source). That's the triangle under each source minus the shadows cast by rock, which can be
+/// filled in one row at a time.
+fn count_floored(cave: &Cave, sources: &[Coordinate]) -> usize {
+    let floor = cave
+        .floor
+        .expect("the closed form only holds for caves with a floor");
+    let mut previous = vec![false; cave.width];
+    let mut count = 0;
+
+    for y in cave.min_y..floor {
+        let current: Vec<bool> = (0..cave.width)
+            .map(|col| {
+                let at = (cave.min_x + col as i32, y);
+                let from_above = previous[col.saturating_sub(1)..(col + 2).min(cave.width)]
+                    .iter()
+                    .any(|&sand| sand);
+
+                cave.get(at) == Some(Tile::Air) && (from_above || sources.contains(&at))
+            })
             .collect();
 
-        let source = (500, 0);
-        let max_y = tiles.keys().map(|&(_, y)| y).max().unwrap();
+        count += current.iter().filter(|&&sand| sand).count();
+        previous = current;
+    }
+
+    count
+}
+
+const SOURCE: Coordinate = (500, 0);
 
-        while let Some(coordinate) = produce(&tiles, source, max_y, is_solid_without_ground) {
-            tiles.insert(coordinate, Tile::Sand);
+impl Cave {
+    /// Every row of the grid plus the floor, as (y, tiles from min_x to the right)
+    fn rows(&self) -> impl Iterator<Item = (i32, Vec<Tile>)> + '_ {
+        let last_y = self.floor.unwrap_or(self.min_y + self.height as i32 - 1);
+
+        (self.min_y..=last_y).map(move |y| {
+            let row = (0..self.width as i32)
+                .map(|col| self.get((self.min_x + col, y)).unwrap_or(Tile::Air))
+                .collect();
+            (y, row)
+        })
+    }
+
+    /// The cave drawn like the puzzle does, with `+` marking each source
+    fn render(&self, sources: &[Coordinate]) -> String {
+        let mut result = String::new();
+
+        for (y, row) in self.rows() {
+            for (col, tile) in row.into_iter().enumerate() {
+                let at = (self.min_x + col as i32, y);
+                result.push(match tile {
+                    Tile::Air if sources.contains(&at) => '+',
+                    Tile::Air => '.',
+                    Tile::Rock => '#',
+                    Tile::Sand => 'o',
+                });
+            }
+            result.push('\n');
         }
 
-        tiles
-            .into_iter()
-            .filter(|&(_, tile)| tile == Tile::Sand)
-            .count()
+        result
     }
 
-    fn part2(input: &str) -> usize {
-        let mut tiles: HashMap<Coordinate, Tile> = parse_input(input)
-            .into_iter()
-            .map(|coord| (coord, Tile::Rock))
-            .collect();
+    /// The cave as a binary PPM image, one pixel per tile
+    fn write_ppm(&self, path: &Path) -> io::Result<()> {
+        let rows: Vec<_> = self.rows().collect();
+        let mut image = format!("P6\n{} {}\n255\n", self.width, rows.len()).into_bytes();
+
+        for (_, row) in rows {
+            for tile in row {
+                image.extend_from_slice(match tile {
+                    Tile::Air => &[20, 20, 30],
+                    Tile::Rock => &[120, 120, 120],
+                    Tile::Sand => &[230, 190, 80],
+                });
+            }
+        }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&image)?;
+        file.flush()
+    }
+}
 
-        let source = (500, 0);
-        let max_y = tiles.keys().map(|&(_, y)| y).max().unwrap();
+fn floor_depth(part: u8) -> Option<i32> {
+    if part == 1 {
+        None
+    } else {
+        Some(2)
+    }
+}
+
+/// Renders the cave once all the sand settled, for either part of the puzzle.
+pub fn render(input: &str, part: u8) -> String {
+    let mut cave = Cave::new(&parse_input(input), &[SOURCE], floor_depth(part));
+    simulate(&mut cave, &[SOURCE], |_, _| {});
+
+    cave.render(&[SOURCE])
+}
 
-        while let Some(coordinate) = produce(&tiles, source, max_y, is_solid_with_ground) {
-            tiles.insert(coordinate, Tile::Sand);
+/// Writes a PPM frame into `directory` every `every` grains, plus the final state, so the frames
+/// can be stitched into an animation. Returns how many frames were written.
+pub fn write_frames(input: &str, part: u8, directory: &Path, every: usize) -> io::Result<usize> {
+    fs::create_dir_all(directory)?;
+    let frame_path = |frame: usize| directory.join(format!("frame_{:06}.ppm", frame));
+
+    let mut cave = Cave::new(&parse_input(input), &[SOURCE], floor_depth(part));
+    let mut frames = 0;
+    let mut result = Ok(());
+
+    let rested = simulate(&mut cave, &[SOURCE], |cave, rested| {
+        if result.is_ok() && rested % every.max(1) == 0 {
+            result = cave.write_ppm(&frame_path(frames));
+            frames += 1;
         }
+    });
+    result?;
 
-        tiles
-            .into_iter()
-            .filter(|&(_, tile)| tile == Tile::Sand)
-            .count()
+    if rested % every.max(1) != 0 {
+        cave.write_ppm(&frame_path(frames))?;
+        frames += 1;
+    }
+
+    Ok(frames)
+}
+
+impl Solve<usize, usize> for Day14 {
+    fn part1(input: &str) -> usize {
+        let mut cave = Cave::new(&parse_input(input), &[SOURCE], None);
+
+        simulate(&mut cave, &[SOURCE], |_, _| {})
+    }
+
+    fn part2(input: &str) -> usize {
+        let cave = Cave::new(&parse_input(input), &[SOURCE], Some(2));
+
+        count_floored(&cave, &[SOURCE])
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
     use super::*;
 
     const EXAMPLE: &str = r#"498,4 -> 498,6 -> 496,6
@@ -146,4 +367,135 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn counts_sand_in_grid() {
+        let sources = [SOURCE];
+        let mut cave = Cave::new(&parse_input(EXAMPLE), &sources, Some(2));
+        let rested = simulate(&mut cave, &sources, |_, _| {});
+
+        let sand = cave
+            .tiles
+            .iter()
+            .filter(|&&tile| tile == Tile::Sand)
+            .count();
+        assert_eq!(sand, rested);
+        assert_eq!(cave.get(SOURCE), Some(Tile::Sand));
+        assert_eq!(cave.get((500, 11)), Some(Tile::Rock));
+    }
+
+    #[test]
+    fn configurable_floor_depth() {
+        let rocks = parse_input(EXAMPLE);
+        let count = |depth| {
+            let mut cave = Cave::new(&rocks, &[SOURCE], Some(depth));
+            simulate(&mut cave, &[SOURCE], |_, _| {})
+        };
+
+        assert_eq!(count(2), 93);
+        assert!(count(5) > count(2));
+    }
+
+    #[test]
+    fn several_sources() {
+        // two sources over a flat floor, their piles merge
+        let rocks: HashSet<Coordinate> = (0..=20).map(|x| (x, 5)).collect();
+        let sources = [(5, 0), (15, 0)];
+        let mut cave = Cave::new(&rocks, &sources, None);
+        let rested = simulate(&mut cave, &sources, |_, _| {});
+
+        let mut single = Cave::new(&rocks, &[(5, 0)], None);
+        let single_rested = simulate(&mut single, &[(5, 0)], |_, _| {});
+
+        assert!(rested > single_rested);
+        assert_eq!(cave.get((5, 0)), Some(Tile::Sand));
+        assert_eq!(cave.get((15, 0)), Some(Tile::Sand));
+    }
+
+    #[test]
+    fn renders_example() {
+        let expected = r#"......+...
+..........
+......o...
+.....ooo..
+....#ooo##
+...o#ooo#.
+..###ooo#.
+....oooo#.
+.o.ooooo#.
+#########."#;
+        // the grid keeps an empty column on each side for sand falling into the abyss
+        let expected: String = expected
+            .lines()
+            .map(|line| format!(".{}.\n", line))
+            .collect();
+
+        assert_eq!(render(EXAMPLE, 1), expected);
+    }
+
+    #[test]
+    fn renders_floor() {
+        let rendered = render(EXAMPLE, 2);
+        let last = rendered.lines().last().unwrap();
+
+        assert_eq!(rendered.lines().count(), 12);
+        assert!(last.chars().all(|ch| ch == '#'));
+        assert_eq!(rendered.matches('o').count(), 93);
+    }
+
+    #[test]
+    fn empty_caves() {
+        let empty = Cave::new(&HashSet::new(), &[], None);
+        assert_eq!(empty.render(&[]), "...\n");
+
+        // without rocks every grain falls into the abyss, or piles up on the floor
+        assert_eq!(Day14::part1("\n"), 0);
+        assert_eq!(Day14::part2(""), 4);
+        assert_eq!(render("", 2), "..o..\n.ooo.\n#####\n");
+    }
+
+    #[test]
+    fn writes_frames() {
+        // unique per run, so parallel or repeated test runs never share a directory
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let directory = std::env::temp_dir().join(format!(
+            "aoc2022_day14_frames_{}_{}",
+            std::process::id(),
+            nanos
+        ));
+
+        let frames = write_frames(EXAMPLE, 1, &directory, 10).unwrap();
+        let first = fs::read(directory.join("frame_000000.ppm")).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        // 24 grains rest, frames after 10 and 20 plus the final one
+        assert_eq!(frames, 3);
+        assert!(first.starts_with(b"P6\n"));
+    }
+
+    #[test]
+    fn closed_form_matches_simulation() {
+        let caves: Vec<(HashSet<Coordinate>, Vec<Coordinate>)> = vec![
+            (parse_input(EXAMPLE), vec![SOURCE]),
+            (parse_input(EXAMPLE), vec![SOURCE, (497, 2)]),
+            // a cup catching sand, with a single rock hanging inside it
+            (
+                parse_input("490,3 -> 490,8 -> 510,8 -> 510,3\n500,5 -> 500,5\n495,12 -> 505,12"),
+                vec![SOURCE],
+            ),
+        ];
+
+        for (rocks, sources) in caves {
+            for depth in [1, 2, 5] {
+                let mut cave = Cave::new(&rocks, &sources, Some(depth));
+                let closed_form = count_floored(&cave, &sources);
+                let simulated = simulate(&mut cave, &sources, |_, _| {});
+
+                assert_eq!(closed_form, simulated);
+            }
+        }
+    }
 }
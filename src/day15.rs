@@ -1,4 +1,5 @@
 use crate::runner::Solve;
+use aoc2022::interval::IntervalSet;
 
 pub struct Day15;
 
@@ -37,87 +38,172 @@ fn ranges_at_row(scanner: Coordinate, beacon: Coordinate, row: i32) -> Option<(i
     }
 }
 
-fn try_merge(range1: (i32, i32), range2: (i32, i32)) -> Option<(i32, i32)> {
-    if range2.0 <= range1.1 + 1 {
-        Some((range1.0, range1.1.max(range2.1)))
-    } else {
-        None
+/// Inclusive rectangle of cells
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Rect {
+    min: Coordinate,
+    max: Coordinate,
+}
+
+impl Rect {
+    fn square(min: i32, max: i32) -> Rect {
+        Rect {
+            min: (min, min),
+            max: (max, max),
+        }
+    }
+
+    fn contains(&self, coord: Coordinate) -> bool {
+        (self.min.0..=self.max.0).contains(&coord.0) && (self.min.1..=self.max.1).contains(&coord.1)
     }
 }
 
-fn merge_ranges(mut ranges: Vec<(i32, i32)>) -> Vec<(i32, i32)> {
-    ranges.sort();
-    let mut result = Vec::new();
-    let mut idx = 0;
-    while idx < ranges.len() {
-        let mut looking_at = idx + 1;
-        let mut current = ranges[idx];
+#[derive(Clone, Copy, Debug)]
+struct Sensor {
+    position: Coordinate,
+    beacon: Coordinate,
+    radius: usize,
+}
 
-        loop {
-            if looking_at >= ranges.len() {
-                break;
-            }
-            if let Some(merged) = try_merge(current, ranges[looking_at]) {
-                current = merged;
-                looking_at += 1;
-            } else {
-                break;
-            }
+impl Sensor {
+    fn covers(&self, coord: Coordinate) -> bool {
+        manhattan_distance(&self.position, &coord) <= self.radius
+    }
+}
+
+/// Every sensor and the diamond of cells it rules out for other beacons
+struct SensorField {
+    sensors: Vec<Sensor>,
+}
+
+impl SensorField {
+    fn parse(input: &str) -> SensorField {
+        let sensors = input
+            .lines()
+            .map(parse_line)
+            .map(|(position, beacon)| Sensor {
+                position,
+                beacon,
+                radius: manhattan_distance(&position, &beacon),
+            })
+            .collect();
+
+        SensorField { sensors }
+    }
+
+    fn covers(&self, coord: Coordinate) -> bool {
+        self.sensors.iter().any(|sensor| sensor.covers(coord))
+    }
+
+    /// Cells covered by a sensor on `row`
+    fn row_coverage(&self, row: i32) -> IntervalSet<i32> {
+        self.sensors
+            .iter()
+            .filter_map(|sensor| ranges_at_row(sensor.position, sensor.beacon, row))
+            .collect()
+    }
+
+    /// Cells on `row` where a beacon can't be, covered cells that aren't a known beacon
+    fn beaconless_in_row(&self, row: i32) -> usize {
+        let beacons: IntervalSet<i32> = self
+            .sensors
+            .iter()
+            .filter(|sensor| sensor.beacon.1 == row)
+            .map(|sensor| (sensor.beacon.0, sensor.beacon.0))
+            .collect();
+
+        self.row_coverage(row).difference(&beacons).len() as usize
+    }
+
+    /// Every cell inside `rect` no sensor covers, found by sweeping its rows
+    fn uncovered_cells(&self, rect: Rect) -> Vec<Coordinate> {
+        (rect.min.1..=rect.max.1)
+            .flat_map(|row| {
+                let uncovered = self.row_coverage(row).complement(rect.min.0, rect.max.0);
+                uncovered
+                    .intervals()
+                    .iter()
+                    .flat_map(|&(from, to)| (from..=to).map(move |col| (col, row)))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Finds an uncovered cell inside `rect` without scanning it, assuming there is at most one.
+    ///
+    /// A single uncovered cell must be boxed in by sensor diamonds or by the edges of `rect`. In
+    /// rotated coordinates (u = x + y, v = x - y) the cells just outside each diamond lie on two
+    /// lines of constant u and two of constant v, so the cell sits where such lines cross each
+    /// other or the edges of `rect`, and only those crossings need checking.
+    fn find_isolated_gap(&self, rect: Rect) -> Option<Coordinate> {
+        let mut u_lines = Vec::new();
+        let mut v_lines = Vec::new();
+        for sensor in self.sensors.iter() {
+            let (x, y) = sensor.position;
+            let reach = sensor.radius as i32 + 1;
+            u_lines.extend([x + y - reach, x + y + reach]);
+            v_lines.extend([x - y - reach, x - y + reach]);
         }
 
-        result.push(current);
+        let (min_x, min_y) = rect.min;
+        let (max_x, max_y) = rect.max;
+        let mut candidates = vec![rect.min, (max_x, min_y), (min_x, max_y), rect.max];
 
-        idx = looking_at;
+        for &u in u_lines.iter() {
+            for &v in v_lines.iter() {
+                if (u + v) % 2 == 0 {
+                    candidates.push(((u + v) / 2, (u - v) / 2));
+                }
+            }
+        }
+        for x in [min_x, max_x] {
+            candidates.extend(u_lines.iter().map(|&u| (x, u - x)));
+            candidates.extend(v_lines.iter().map(|&v| (x, x - v)));
+        }
+        for y in [min_y, max_y] {
+            candidates.extend(u_lines.iter().map(|&u| (u - y, y)));
+            candidates.extend(v_lines.iter().map(|&v| (v + y, y)));
+        }
+
+        candidates
+            .into_iter()
+            .find(|&candidate| rect.contains(candidate) && !self.covers(candidate))
     }
+}
 
-    result
+fn tuning_frequency((x, y): Coordinate) -> u64 {
+    x as u64 * 4_000_000 + y as u64
 }
 
-/// Constraint: ranges must be merged and sorted
-fn find_gap(ranges: &[(i32, i32)]) -> Vec<i32> {
-    ranges
-        .windows(2)
-        .flat_map(|window| window[0].1 + 1..window[1].0)
-        .filter(|&value| (0..=4000000).contains(&value))
-        .collect()
+fn solve_part1(input: &str, row: i32) -> usize {
+    SensorField::parse(input).beaconless_in_row(row)
 }
 
-fn solve_part1(input: &str, row: i32) -> i32 {
-    let ranges: Vec<(i32, i32)> = input
-        .lines()
-        .map(parse_line)
-        .filter_map(|(sensor, beacon)| ranges_at_row(sensor, beacon, row))
-        .collect();
+fn solve_part2(input: &str, max: i32) -> u64 {
+    let field = SensorField::parse(input);
 
-    merge_ranges(ranges)
+    field
+        .find_isolated_gap(Rect::square(0, max))
+        .map(tuning_frequency)
+        .expect("no uncovered cell in the search area")
+}
+
+/// Lists every cell no sensor covers inside the rectangle from `min` to `max`, one per line.
+pub fn uncovered(input: &str, min: Coordinate, max: Coordinate) -> String {
+    SensorField::parse(input)
+        .uncovered_cells(Rect { min, max })
         .into_iter()
-        .map(|(from, to)| to - from)
-        .sum()
+        .map(|(x, y)| format!("{},{}\n", x, y))
+        .collect()
 }
 
-impl Solve<i32, u64> for Day15 {
-    fn part1(input: &str) -> i32 {
+impl Solve<usize, u64> for Day15 {
+    fn part1(input: &str) -> usize {
         solve_part1(input, 2_000_000)
     }
 
     fn part2(input: &str) -> u64 {
-        let scanners: Vec<_> = input.lines().map(parse_line).collect();
-        for row in 0..4_000_000 {
-            let ranges: Vec<(i32, i32)> = scanners
-                .iter()
-                .cloned()
-                .filter_map(|(sensor, beacon)| ranges_at_row(sensor, beacon, row))
-                .collect();
-
-            let merged = merge_ranges(ranges);
-            let possible_cols = find_gap(&merged);
-
-            if let Some(&col) = possible_cols.first() {
-                return col as u64 * 4_000_000u64 + row as u64;
-            }
-        }
-
-        0
+        solve_part2(input, 4_000_000)
     }
 }
 
@@ -151,9 +237,55 @@ Sensor at x=20, y=1: closest beacon is at x=15, y=3
 
     #[test]
     fn example_p2() {
-        let result = Day15::part2(EXAMPLE);
+        let result = solve_part2(EXAMPLE, 20);
         let expected = 56000011;
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn uncovered_cells_in_rect() {
+        let field = SensorField::parse(EXAMPLE);
+        let rect = Rect {
+            min: (-12, -3),
+            max: (30, 25),
+        };
+
+        let expected: Vec<Coordinate> = (rect.min.1..=rect.max.1)
+            .flat_map(|y| (rect.min.0..=rect.max.0).map(move |x| (x, y)))
+            .filter(|&cell| !field.covers(cell))
+            .collect();
+
+        assert_eq!(field.uncovered_cells(rect), expected);
+        assert_eq!(field.uncovered_cells(Rect::square(0, 20)), vec![(14, 11)]);
+    }
+
+    #[test]
+    fn gap_on_rect_edges() {
+        let field = SensorField::parse(EXAMPLE);
+
+        // the gap is boxed in by diamonds on one side and the rectangle on the other
+        for rect in [
+            Rect {
+                min: (14, 11),
+                max: (20, 20),
+            },
+            Rect {
+                min: (0, 0),
+                max: (14, 11),
+            },
+            Rect {
+                min: (14, 0),
+                max: (20, 11),
+            },
+        ] {
+            assert_eq!(field.uncovered_cells(rect), vec![(14, 11)]);
+            assert_eq!(field.find_isolated_gap(rect), Some((14, 11)));
+        }
+    }
+
+    #[test]
+    fn tuning_frequency_example() {
+        assert_eq!(tuning_frequency((14, 11)), 56000011);
+    }
 }
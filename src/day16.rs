@@ -1,6 +1,7 @@
-use std::collections::{hash_map::Entry, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::collections::{hash_map::Entry, HashMap};
+use std::fmt::{self, Display};
 
-use crate::runner::Solve;
+use crate::runner::{SearchStats, Solve};
 
 pub struct Day16;
 
@@ -35,239 +36,466 @@ fn parse_valve(input: &str) -> Valve {
     }
 }
 
-fn reachable_from(
-    map: &HashMap<String, Valve>,
-    position: &str,
-    visited: &mut HashSet<String>,
-) -> HashMap<String, usize> {
-    let node = map.get(position).unwrap();
-
-    let mut result = HashMap::new();
-
-    for (connection, &cost) in node.connections.iter() {
-        let neighbour = map.get(connection).unwrap();
-        if visited.insert(connection.to_owned()) {
-            if neighbour.flow_rate == 0 {
-                result.extend(
-                    reachable_from(map, connection, visited)
-                        .into_iter()
-                        .map(|(k, v)| (k, v + cost)),
-                );
-            } else {
-                result.insert(connection.clone(), cost);
-            }
-        }
-    }
-
-    result
+/// Valves worth opening and the start, interned to small ids so sets of them fit in a u64
+/// bitmask, with the length of the shortest path between every pair of them.
+///
+/// The valves worth opening get the lowest ids, so sets of them are numbers below
+/// `1 << flowing`, and the start comes last unless it is worth opening itself.
+#[derive(Debug)]
+struct Network {
+    names: Vec<String>,
+    flow_rates: Vec<usize>,
+    distances: Vec<Vec<usize>>,
+    start: usize,
+    flowing: usize,
 }
 
-fn prune_zero_flow(map: HashMap<String, Valve>) -> HashMap<String, Valve> {
-    let mut result = HashMap::new();
-
-    for name in map.keys() {
-        let node = map.get(name).unwrap();
-        if name == "AA" || node.flow_rate > 0 {
-            let mut visited = HashSet::new();
-            visited.insert(name.to_owned());
-            let connections = reachable_from(&map, name, &mut visited);
-            let valve = Valve {
-                connections,
-                name: name.to_owned(),
-                flow_rate: node.flow_rate,
-            };
+impl Network {
+    /// Contracts the scanned tunnels down to the valves worth opening and `start`
+    fn new(map: &HashMap<String, Valve>, start: &str) -> Result<Network, String> {
+        check_valves(map, start)?;
+        let mut names: Vec<String> = map.keys().cloned().collect();
+        names.sort_by_key(|name| (map[name].flow_rate == 0, name != start, name.clone()));
+        let flowing = names.iter().filter(|name| map[*name].flow_rate > 0).count();
+        if flowing > 64 {
+            return Err(format!(
+                "{} valves are worth opening, at most 64 fit in a set of open valves",
+                flowing
+            ));
+        }
 
-            result.insert(name.to_owned(), valve);
+        let all_distances = floyd_warshall(map, &names);
+        let kept = if map[start].flow_rate > 0 {
+            flowing
+        } else {
+            flowing + 1
+        };
+
+        Ok(Network {
+            start: names.iter().position(|name| name == start).unwrap(),
+            flow_rates: names[..kept]
+                .iter()
+                .map(|name| map[name].flow_rate)
+                .collect(),
+            distances: all_distances[..kept]
+                .iter()
+                .map(|row| row[..kept].to_vec())
+                .collect(),
+            names: names[..kept].to_vec(),
+            flowing,
+        })
+    }
+
+    /// Pairs of valves with a shortest path between them that doesn't pass another valve of the
+    /// network, and its length
+    fn corridors(&self) -> Vec<(usize, usize, usize)> {
+        let valves = self.names.len();
+        let mut result = Vec::new();
+
+        for from in 0..valves {
+            for to in from + 1..valves {
+                let distance = self.distances[from][to];
+                let through_other = (0..valves).any(|other| {
+                    other != from
+                        && other != to
+                        && self.distances[from][other].saturating_add(self.distances[other][to])
+                            == distance
+                });
+                if distance != usize::MAX && !through_other {
+                    result.push((from, to, distance));
+                }
+            }
         }
+
+        result
     }
-    result
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct State {
-    pressure: usize,
-    units: Vec<String>,
-    minutes: Vec<usize>,
-    open: BTreeSet<String>,
+/// Makes sure `start` and every valve a tunnel leads to are scanned
+fn check_valves(map: &HashMap<String, Valve>, start: &str) -> Result<(), String> {
+    let mut names: Vec<&String> = map.keys().collect();
+    names.sort();
+    let tunnels = names
+        .iter()
+        .flat_map(|&name| map[name].connections.keys().map(String::as_str));
+
+    match std::iter::once(start)
+        .chain(tunnels)
+        .find(|name| !map.contains_key(*name))
+    {
+        Some(name) => Err(format!("no valve named {}", name)),
+        None => Ok(()),
+    }
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other
-            .minutes
-            .cmp(&self.minutes)
-            .then_with(|| self.pressure.cmp(&other.pressure))
-            .then_with(|| other.open.len().cmp(&self.open.len()))
+/// Length of the shortest path between every pair of valves, in the order of `names`, usize::MAX
+/// when there is none
+fn floyd_warshall(map: &HashMap<String, Valve>, names: &[String]) -> Vec<Vec<usize>> {
+    let ids: HashMap<&str, usize> = names
+        .iter()
+        .enumerate()
+        .map(|(id, name)| (name.as_str(), id))
+        .collect();
+
+    let mut distances = vec![vec![usize::MAX; names.len()]; names.len()];
+    for (from, name) in names.iter().enumerate() {
+        distances[from][from] = 0;
+        for (neighbour, &cost) in map[name].connections.iter() {
+            let to = ids[neighbour.as_str()];
+            distances[from][to] = distances[from][to].min(cost);
+        }
     }
-}
 
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    for through in 0..names.len() {
+        for from in 0..names.len() {
+            for to in 0..names.len() {
+                let distance = distances[from][through].saturating_add(distances[through][to]);
+                if distance < distances[from][to] {
+                    distances[from][to] = distance;
+                }
+            }
+        }
     }
-}
 
-#[derive(Debug, Hash, Eq, PartialEq)]
-struct PressureKey {
-    open: BTreeSet<String>,
-    units: Vec<String>,
+    distances
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct PressureValue {
-    pressure: usize,
-    minutes: Vec<usize>,
+/// Valves opened by one agent, with the minute each is opened at
+type Route = Vec<(usize, usize)>;
+
+/// Best pressure a single agent releases for each set of valves it can open in time, with the
+/// route that releases it
+type BestRoutes = HashMap<u64, (usize, Route)>;
+
+/// Tries every order of opening valves, keeping the best route per set of opened valves
+struct Explorer<'a> {
+    network: &'a Network,
+    minutes: usize,
+    route: Route,
+    best: BestRoutes,
+    /// Minute and pressure of the visits to each (position, open valves) that no other visit
+    /// beat, a visit later with less pressure can't lead anywhere better
+    visits: HashMap<(usize, u64), Vec<(usize, usize)>>,
+    /// Only the single best route is wanted, so routes that can't beat it are pruned
+    only_best: bool,
+    best_pressure: usize,
+    best_route: Route,
+    /// Routes whose next valves were tried, and routes dropped as they couldn't beat the best one
+    /// so far or an earlier route to the same valve with the same valves open
+    stats: SearchStats,
 }
 
-impl Ord for PressureValue {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.pressure
-            .cmp(&other.pressure)
-            .then_with(|| other.minutes.cmp(&self.minutes))
+impl Explorer<'_> {
+    fn new(network: &Network, minutes: usize, only_best: bool) -> Explorer<'_> {
+        Explorer {
+            network,
+            minutes,
+            route: Vec::new(),
+            best: HashMap::new(),
+            visits: HashMap::new(),
+            only_best,
+            best_pressure: 0,
+            best_route: Vec::new(),
+            stats: SearchStats::default(),
+        }
     }
-}
 
-impl PartialOrd for PressureValue {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
+    /// Most pressure a route could release if every closed valve was walked to straight from
+    /// `position`, as if opening the others took no time
+    fn upper_bound(&self, position: usize, minute: usize, open: u64, pressure: usize) -> usize {
+        let network = self.network;
+        let closed = (0..network.flowing).filter(|valve| open & (1 << valve) == 0);
+
+        pressure
+            + closed
+                .map(|valve| {
+                    let opened = network.distances[position][valve].saturating_add(minute + 1);
+                    self.minutes.saturating_sub(opened) * network.flow_rates[valve]
+                })
+                .sum::<usize>()
     }
-}
 
-fn should_relax(state: &State, pressures: &mut HashMap<PressureKey, PressureValue>) -> bool {
-    let pressure_key = PressureKey {
-        open: state.open.clone(),
-        units: state.units.clone(),
-    };
+    /// Whether an earlier visit got to `position` with the same valves open no later and with at
+    /// least as much pressure, remembering this visit when it didn't
+    fn dominated(&mut self, position: usize, minute: usize, open: u64, pressure: usize) -> bool {
+        let visits = self.visits.entry((position, open)).or_default();
+        if visits
+            .iter()
+            .any(|&(earlier, released)| earlier <= minute && released >= pressure)
+        {
+            return true;
+        }
 
-    let pressure_value = PressureValue {
-        pressure: state.pressure,
-        minutes: state.minutes.clone(),
-    };
+        visits.retain(|&(later, released)| later < minute || released > pressure);
+        visits.push((minute, pressure));
+        false
+    }
 
-    let best_value = pressures
-        .entry(pressure_key)
-        .or_insert_with(|| pressure_value.clone());
+    fn explore(&mut self, position: usize, minute: usize, open: u64, pressure: usize) {
+        if self.dominated(position, minute, open, pressure) {
+            self.stats.pruned += 1;
+            return;
+        }
 
-    *best_value > pressure_value
-}
+        match self.best.entry(open) {
+            Entry::Occupied(mut o) if o.get().0 < pressure => {
+                o.insert((pressure, self.route.clone()));
+            }
+            Entry::Occupied(_) => (),
+            Entry::Vacant(v) => {
+                v.insert((pressure, self.route.clone()));
+            }
+        }
+        if pressure > self.best_pressure {
+            self.best_pressure = pressure;
+            self.best_route = self.route.clone();
+        }
 
-fn should_proceed(state: &State, pressures: &mut HashMap<PressureKey, PressureValue>) -> bool {
-    let pressure_key = PressureKey {
-        open: state.open.clone(),
-        units: state.units.clone(),
-    };
+        if self.only_best
+            && self.upper_bound(position, minute, open, pressure) <= self.best_pressure
+        {
+            self.stats.pruned += 1;
+            return;
+        }
+        self.stats.expanded += 1;
 
-    let pressure_value = PressureValue {
-        pressure: state.pressure,
-        minutes: state.minutes.clone(),
-    };
+        let network = self.network;
+        for next in 0..network.flowing {
+            if open & (1 << next) != 0 {
+                continue;
+            }
 
-    match pressures.entry(pressure_key) {
-        Entry::Occupied(mut o) => {
-            let best_pressure = o.get_mut();
-            if *best_pressure >= pressure_value {
-                false
-            } else {
-                *best_pressure = pressure_value;
-                true
+            // unreachable valves are usize::MAX away
+            let opened = network.distances[position][next].saturating_add(minute + 1);
+            if opened >= self.minutes {
+                continue;
             }
-        }
-        Entry::Vacant(v) => {
-            v.insert(pressure_value);
-            true
+
+            self.route.push((next, opened));
+            self.explore(
+                next,
+                opened,
+                open | (1 << next),
+                pressure + (self.minutes - opened) * network.flow_rates[next],
+            );
+            self.route.pop();
         }
     }
 }
 
-fn dfs(map: &HashMap<String, Valve>, units: Vec<String>, minute_start: usize) -> usize {
-    let start = State {
-        pressure: 0,
-        minutes: vec![minute_start; units.len()],
-        units,
-        open: BTreeSet::new(),
-    };
-    let mut pressures: HashMap<PressureKey, PressureValue> = HashMap::new();
-    pressures.insert(
-        PressureKey {
-            open: start.open.clone(),
-            units: start.units.clone(),
-        },
-        PressureValue {
-            pressure: 0,
-            minutes: start.minutes.clone(),
-        },
-    );
-    let mut queue = BinaryHeap::new();
-    queue.push(start);
-
-    let mut best = 0;
-
-    while let Some(current) = queue.pop() {
-        if current.minutes.iter().any(|&minute| minute >= 30) {
-            continue;
+/// Best total of one agent within a share of `set` and a team within the rest of it, with that
+/// share
+fn best_share(within: &[(usize, u64)], team: &[usize], set: u64) -> (usize, u64) {
+    let mut best = (0, 0);
+    let mut share = set;
+    loop {
+        let total = within[share as usize].0 + team[(set ^ share) as usize];
+        best = best.max((total, share));
+        if share == 0 {
+            return best;
         }
+        share = (share - 1) & set;
+    }
+}
 
-        if current.pressure > best {
-            best = current.pressure;
+/// Routes of `agents` agents opening disjoint sets of valves, releasing the most pressure
+/// together.
+///
+/// Each agent beyond the second takes `3 ^ flowing` steps, going through every share of every
+/// set of valves.
+fn split_between(network: &Network, best: &BestRoutes, agents: usize) -> Vec<Route> {
+    let sets = 1 << network.flowing;
+
+    // best single route only opening valves of each set, with the set it opens
+    let mut within: Vec<(usize, u64)> = vec![(0, 0); sets];
+    for (&open, &(pressure, _)) in best.iter() {
+        within[open as usize] = within[open as usize].max((pressure, open));
+    }
+    for valve in 0..network.flowing {
+        for set in 0..sets {
+            if set & (1 << valve) != 0 {
+                within[set] = within[set].max(within[set ^ (1 << valve)]);
+            }
         }
+    }
 
-        let (unit_idx, _cost) = current
-            .minutes
-            .iter()
-            .enumerate()
-            .min_by_key(|(_, minute)| *minute)
-            .unwrap();
+    // teams[n][set] is the best total of n + 1 agents only opening valves of the set
+    let mut teams: Vec<Vec<usize>> = vec![within.iter().map(|&(pressure, _)| pressure).collect()];
+    for _ in 2..agents {
+        let team = teams.last().unwrap();
+        let next = (0..sets as u64)
+            .map(|set| best_share(&within, team, set).0)
+            .collect();
+        teams.push(next);
+    }
 
-        let current_valve = map.get(&current.units[unit_idx]).unwrap();
+    let mut routes = Vec::new();
+    let mut remaining = (1 << network.flowing) - 1;
+    for team in teams[..agents - 1].iter().rev() {
+        let (_, share) = best_share(&within, team, remaining);
+        routes.push(best[&within[share as usize].1].1.clone());
+        remaining ^= share;
+    }
+    routes.push(best[&within[remaining as usize].1].1.clone());
 
-        if should_relax(&current, &mut pressures) {
-            continue;
-        }
+    routes
+}
 
-        for (neighbour, cost) in current_valve.connections.iter() {
-            for activating in [true, false] {
-                if activating && current.open.contains(neighbour) {
-                    continue;
-                }
+/// One valve opened by an agent, minutes counted from when the agents set off like the puzzle
+/// does: the walk takes the minutes after `departure` up to `opened - 1`, then the valve is opened
+/// during minute `opened`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opening {
+    pub valve: String,
+    pub departure: usize,
+    pub opened: usize,
+    pub released: usize,
+}
 
-                let mut next_open = current.open.clone();
+/// Best pressure and the valves each agent opens to get it, in order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Plan {
+    pub pressure: usize,
+    pub agents: Vec<Vec<Opening>>,
+    /// Work done finding the routes
+    pub search: SearchStats,
+}
 
-                if activating {
-                    next_open.insert(neighbour.to_owned());
-                }
+impl Plan {
+    fn new(network: &Network, routes: Vec<Route>, minutes: usize, search: SearchStats) -> Plan {
+        let agents: Vec<Vec<Opening>> = routes
+            .into_iter()
+            .map(|route| {
+                let mut departure = 0;
+                route
+                    .into_iter()
+                    .map(|(valve, opened)| {
+                        let opening = Opening {
+                            valve: network.names[valve].clone(),
+                            departure,
+                            opened,
+                            released: (minutes - opened) * network.flow_rates[valve],
+                        };
+                        departure = opened;
+                        opening
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Plan {
+            pressure: agents
+                .iter()
+                .flatten()
+                .map(|opening| opening.released)
+                .sum(),
+            agents,
+            search,
+        }
+    }
 
-                let mut next_minutes = current.minutes.clone();
-                next_minutes[unit_idx] += cost + usize::from(activating);
+    fn agent_name(&self, agent: usize) -> String {
+        match (agent, self.agents.len()) {
+            (0, _) => String::from("you"),
+            (_, 2) => String::from("the elephant"),
+            (agent, _) => format!("elephant {}", agent),
+        }
+    }
+}
 
-                if next_minutes[unit_idx] >= 30 {
-                    continue;
+/// Every walk and opening of every agent, in the order they happen
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut events: Vec<(usize, usize, String)> = Vec::new();
+        for (agent, openings) in self.agents.iter().enumerate() {
+            for opening in openings {
+                let walk = opening.opened - 1 - opening.departure;
+                // a valve at the start is opened without walking
+                if walk > 0 {
+                    let plural = if walk == 1 { "" } else { "s" };
+                    events.push((
+                        opening.opened - 1,
+                        agent,
+                        format!("walk to {} ({} minute{})", opening.valve, walk, plural),
+                    ));
                 }
+                events.push((
+                    opening.opened,
+                    agent,
+                    format!("open {}, releasing {}", opening.valve, opening.released),
+                ));
+            }
+        }
+        events.sort();
+
+        for (minute, agent, event) in events {
+            writeln!(
+                f,
+                "minute {:2}, {}: {}",
+                minute,
+                self.agent_name(agent),
+                event
+            )?;
+        }
+        writeln!(f, "total pressure released: {}", self.pressure)
+    }
+}
 
-                let next_valve = map.get(neighbour).unwrap();
-
-                let next_pressure = current.pressure
-                    + usize::from(activating)
-                        * (30 - next_minutes[unit_idx])
-                        * next_valve.flow_rate;
+/// Most valves worth opening that routes can be split between several agents for, the split
+/// keeps a table entry per set of them
+const MAX_SPLIT_VALVES: usize = 20;
+
+/// What-if version of the puzzle: agents set off together from `start` once `teaching` minutes of
+/// the `minutes` budget are spent, then open disjoint sets of valves.
+///
+/// Part 1 is one agent with 30 minutes and no teaching, part 2 two agents with 30 minutes and 4
+/// of them spent teaching the elephant.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValveProblem {
+    pub start: String,
+    pub minutes: usize,
+    pub agents: usize,
+    pub teaching: usize,
+}
 
-                let mut next_units = current.units.clone();
-                next_units[unit_idx] = neighbour.clone();
+impl ValveProblem {
+    fn puzzle(part: u8) -> ValveProblem {
+        let (agents, teaching) = match part {
+            1 => (1, 0),
+            _ => (2, 4),
+        };
+        ValveProblem {
+            start: String::from("AA"),
+            minutes: 30,
+            agents,
+            teaching,
+        }
+    }
 
-                let next_state = State {
-                    pressure: next_pressure,
-                    units: next_units,
-                    minutes: next_minutes,
-                    open: next_open,
-                };
+    pub fn solve(&self, input: &str) -> Result<Plan, String> {
+        if self.agents == 0 {
+            return Err(String::from("at least one agent is needed"));
+        }
 
-                if should_proceed(&next_state, &mut pressures) {
-                    queue.push(next_state);
-                }
-            }
+        let network = Network::new(&parse_input(input), &self.start)?;
+        if self.agents > 1 && network.flowing > MAX_SPLIT_VALVES {
+            return Err(format!(
+                "{} valves are worth opening, at most {} can be split between agents",
+                network.flowing, MAX_SPLIT_VALVES
+            ));
         }
-    }
 
-    best
+        let minutes = self.minutes.saturating_sub(self.teaching);
+        let mut explorer = Explorer::new(&network, minutes, self.agents == 1);
+        explorer.explore(network.start, 0, 0, 0);
+
+        let routes = if self.agents == 1 {
+            vec![explorer.best_route]
+        } else {
+            split_between(&network, &explorer.best, self.agents)
+        };
+        Ok(Plan::new(&network, routes, minutes, explorer.stats))
+    }
 }
 
 fn parse_input(input: &str) -> HashMap<String, Valve> {
@@ -278,21 +506,82 @@ fn parse_input(input: &str) -> HashMap<String, Valve> {
         .collect()
 }
 
+/// The plan behind the answer of a part, as a timeline followed by the search statistics
+pub fn plan(input: &str, part: u8) -> Result<String, String> {
+    let plan = ValveProblem::puzzle(part).solve(input)?;
+    Ok(format!("{}search: {}\n", plan, plan.search))
+}
+
+fn dot_valve(name: &str, flow_rate: usize, start: &str) -> String {
+    let shape = if name == start {
+        ", shape=doublecircle"
+    } else {
+        ""
+    };
+    format!(
+        "    {} [label=\"{}\\nflow {}\"{}];\n",
+        name, name, flow_rate, shape
+    )
+}
+
+/// Graphviz DOT of the scanned tunnels, or of the network contracted to the valves worth opening
+/// and `start`, its edges labelled with their lengths.
+pub fn dot(input: &str, contracted: bool, start: &str) -> Result<String, String> {
+    let map = parse_input(input);
+    check_valves(&map, start)?;
+    let mut result = String::from("graph valves {\n");
+
+    if contracted {
+        let network = Network::new(&map, start)?;
+        for (id, name) in network.names.iter().enumerate() {
+            result += &dot_valve(name, network.flow_rates[id], start);
+        }
+        for (from, to, distance) in network.corridors() {
+            result += &format!(
+                "    {} -- {} [label=\"{}\"];\n",
+                network.names[from], network.names[to], distance
+            );
+        }
+    } else {
+        let mut names: Vec<&String> = map.keys().collect();
+        names.sort();
+        for name in names.iter() {
+            result += &dot_valve(name, map[*name].flow_rate, start);
+        }
+        // tunnels are listed from both ends, draw each once
+        let mut tunnels: Vec<(&String, &String)> = names
+            .iter()
+            .flat_map(|&name| {
+                map[name]
+                    .connections
+                    .keys()
+                    .map(move |neighbour| (name.min(neighbour), name.max(neighbour)))
+            })
+            .collect();
+        tunnels.sort();
+        tunnels.dedup();
+        for (from, to) in tunnels {
+            result += &format!("    {} -- {};\n", from, to);
+        }
+    }
+    result += "}\n";
+
+    Ok(result)
+}
+
 impl Solve<usize, usize> for Day16 {
     fn part1(input: &str) -> usize {
-        let map = parse_input(input);
-        let map = prune_zero_flow(map);
-        dfs(&map, vec![String::from("AA")], 0)
+        ValveProblem::puzzle(1).solve(input).unwrap().pressure
     }
     fn part2(input: &str) -> usize {
-        let map = parse_input(input);
-        let map = prune_zero_flow(map);
-        dfs(&map, vec![String::from("AA"), String::from("AA")], 4)
+        ValveProblem::puzzle(2).solve(input).unwrap().pressure
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashSet;
+
     use super::*;
 
     const EXAMPLE: &str = r#"Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
@@ -324,6 +613,283 @@ Valve JJ has flow rate=21; tunnel leads to valve II"#;
         );
     }
 
+    #[test]
+    fn network_distances() {
+        let network = Network::new(&parse_input(EXAMPLE), "AA").unwrap();
+        let id = |name: &str| network.names.iter().position(|n| n == name).unwrap();
+
+        assert_eq!(network.names.len(), 7);
+        assert_eq!(network.distances[id("AA")][id("JJ")], 2);
+        assert_eq!(network.distances[id("AA")][id("HH")], 5);
+        assert_eq!(network.distances[id("JJ")][id("CC")], 4);
+        assert_eq!(network.flowing, 6);
+        assert_eq!(network.names, ["BB", "CC", "DD", "EE", "HH", "JJ", "AA"]);
+        assert_eq!(network.start, 6);
+    }
+
+    #[test]
+    fn distances_through_visited_valves() {
+        // a depth first search marking valves visited globally can reach XX through YY first,
+        // and then never try the direct tunnel from AA
+        let shortcut = r#"Valve AA has flow rate=0; tunnels lead to valves XX, YY
+Valve YY has flow rate=0; tunnels lead to valves AA, XX
+Valve XX has flow rate=0; tunnels lead to valves AA, YY, BB
+Valve BB has flow rate=5; tunnel leads to valve XX"#;
+        let network = Network::new(&parse_input(shortcut), "AA").unwrap();
+        assert_eq!(network.names, ["BB", "AA"]);
+        assert_eq!(network.distances[1][0], 2);
+
+        // going around the ring marks SS visited before the short way through it is tried
+        let ring = r#"Valve AA has flow rate=0; tunnels lead to valves PP, SS
+Valve PP has flow rate=0; tunnels lead to valves AA, QQ
+Valve QQ has flow rate=0; tunnels lead to valves PP, RR
+Valve RR has flow rate=0; tunnels lead to valves QQ, SS
+Valve SS has flow rate=0; tunnels lead to valves RR, AA, BB
+Valve BB has flow rate=7; tunnels lead to valves SS, CC
+Valve CC has flow rate=3; tunnel leads to valve BB"#;
+        let network = Network::new(&parse_input(ring), "AA").unwrap();
+        assert_eq!(network.names, ["BB", "CC", "AA"]);
+        assert_eq!(network.distances[2], [2, 3, 0]);
+        assert_eq!(network.corridors(), vec![(0, 1, 1), (0, 2, 2)]);
+
+        let problem = ValveProblem {
+            start: String::from("AA"),
+            minutes: 8,
+            agents: 1,
+            teaching: 0,
+        };
+        assert_eq!(problem.solve(ring).unwrap().pressure, 5 * 7 + 3 * 3);
+    }
+
+    #[test]
+    fn dot_graphs() {
+        let raw = dot(EXAMPLE, false, "AA").unwrap();
+        assert!(raw
+            .starts_with("graph valves {\n    AA [label=\"AA\\nflow 0\", shape=doublecircle];\n"));
+        assert_eq!(raw.matches(" -- ").count(), 10);
+
+        let contracted = dot(EXAMPLE, true, "AA").unwrap();
+        assert!(contracted.contains("    EE -- HH [label=\"3\"];\n"));
+        assert!(contracted.contains("    JJ -- AA [label=\"2\"];\n"));
+        assert!(!contracted.contains("II"));
+        assert_eq!(contracted.matches(" -- ").count(), 7);
+
+        let from_bb = dot(EXAMPLE, true, "BB").unwrap();
+        assert!(from_bb.contains("    BB [label=\"BB\\nflow 13\", shape=doublecircle];\n"));
+        assert!(!from_bb.contains("AA"));
+        assert!(dot(EXAMPLE, true, "ZZ").is_err());
+        assert!(dot(EXAMPLE, false, "ZZ").is_err());
+
+        let dead_end = "Valve AA has flow rate=0; tunnels lead to valves BB, ZZ\nValve BB has flow rate=1; tunnel leads to valve AA";
+        let missing = Err(String::from("no valve named ZZ"));
+        assert_eq!(dot(dead_end, false, "AA"), missing);
+        assert_eq!(dot(dead_end, true, "AA"), missing);
+    }
+
+    #[test]
+    fn example_plan() {
+        let plan = ValveProblem::puzzle(1).solve(EXAMPLE).unwrap();
+        let openings: Vec<(&str, usize)> = plan.agents[0]
+            .iter()
+            .map(|opening| (opening.valve.as_str(), opening.opened))
+            .collect();
+
+        assert_eq!(
+            openings,
+            vec![
+                ("DD", 2),
+                ("BB", 5),
+                ("JJ", 9),
+                ("HH", 17),
+                ("EE", 21),
+                ("CC", 24)
+            ]
+        );
+        assert!(plan.to_string().starts_with(
+            "minute  1, you: walk to DD (1 minute)\nminute  2, you: open DD, releasing 560\n"
+        ));
+
+        let plan = ValveProblem::puzzle(2).solve(EXAMPLE).unwrap();
+        assert_eq!(plan.agents.len(), 2);
+        assert_eq!(plan.pressure, 1707);
+        assert!(plan
+            .to_string()
+            .ends_with("total pressure released: 1707\n"));
+    }
+
+    /// Best pressure for every set of valves one agent can open, trying every route without
+    /// pruning any, and how many routes there are
+    fn every_route(
+        network: &Network,
+        minutes: usize,
+        (position, minute, open, pressure): (usize, usize, u64, usize),
+        best: &mut HashMap<u64, usize>,
+    ) -> usize {
+        let entry = best.entry(open).or_default();
+        *entry = (*entry).max(pressure);
+
+        let mut routes = 1;
+        for next in (0..network.flowing).filter(|next| open & (1 << next) == 0) {
+            let opened = network.distances[position][next].saturating_add(minute + 1);
+            if opened < minutes {
+                let released = (minutes - opened) * network.flow_rates[next];
+                let state = (next, opened, open | (1 << next), pressure + released);
+                routes += every_route(network, minutes, state, best);
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn pruning_keeps_best_routes() {
+        let map = parse_input(EXAMPLE);
+
+        // DD, HH and JJ release pressure themselves, so opening the start takes a single minute
+        for start in ["AA", "DD", "HH", "JJ"] {
+            let network = Network::new(&map, start).unwrap();
+            for minutes in [2, 5, 12, 30] {
+                let mut expected = HashMap::new();
+                every_route(&network, minutes, (network.start, 0, 0, 0), &mut expected);
+
+                let mut every_set = Explorer::new(&network, minutes, false);
+                every_set.explore(network.start, 0, 0, 0);
+                let found: HashMap<u64, usize> = every_set
+                    .best
+                    .iter()
+                    .map(|(&open, &(pressure, _))| (open, pressure))
+                    .collect();
+                assert_eq!(found, expected);
+
+                let mut bounded = Explorer::new(&network, minutes, true);
+                bounded.explore(network.start, 0, 0, 0);
+                assert_eq!(bounded.best_pressure, *expected.values().max().unwrap());
+            }
+        }
+
+        let network = Network::new(&map, "AA").unwrap();
+        let routes = every_route(&network, 30, (network.start, 0, 0, 0), &mut HashMap::new());
+        let mut bounded = Explorer::new(&network, 30, true);
+        bounded.explore(network.start, 0, 0, 0);
+        assert!(bounded.stats.pruned > 0);
+        assert!(bounded.stats.expanded * 10 <= routes);
+    }
+
+    #[test]
+    fn what_if_problems() {
+        let pressure = |start: &str, minutes, agents, teaching| {
+            let problem = ValveProblem {
+                start: String::from(start),
+                minutes,
+                agents,
+                teaching,
+            };
+            problem.solve(EXAMPLE).map(|plan| plan.pressure)
+        };
+
+        assert_eq!(pressure("AA", 40, 1, 0), Ok(2461));
+        assert_eq!(pressure("AA", 30, 3, 4), Ok(1794));
+        assert_eq!(pressure("AA", 40, 4, 4), Ok(2635));
+        assert_eq!(pressure("JJ", 24, 2, 4), Ok(1212));
+        assert_eq!(pressure("AA", 3, 2, 4), Ok(0));
+        assert!(pressure("ZZ", 30, 1, 0).is_err());
+        assert!(pressure("AA", 30, 0, 0).is_err());
+
+        let dead_end =
+            ValveProblem::puzzle(1).solve("Valve AA has flow rate=0; tunnel leads to valve ZZ");
+        assert_eq!(
+            dead_end.map(|plan| plan.pressure),
+            Err(String::from("no valve named ZZ"))
+        );
+    }
+
+    /// A ring of 60 valves with tunnels 7 valves ahead, every fourth one releasing pressure
+    fn ring_of_valves() -> String {
+        let name = |valve: usize| {
+            let letter = |n: usize| char::from(b'A' + n as u8);
+            format!("{}{}", letter(valve / 26), letter(valve % 26))
+        };
+        (0..60)
+            .map(|valve| {
+                let flow_rate = if valve % 4 == 3 {
+                    valve * 7 % 23 + 1
+                } else {
+                    0
+                };
+                let mut tunnels = [valve + 1, valve + 59, valve + 7, valve + 53].map(|v| v % 60);
+                tunnels.sort_unstable();
+                format!(
+                    "Valve {} has flow rate={}; tunnels lead to valves {}\n",
+                    name(valve),
+                    flow_rate,
+                    tunnels.map(name).join(", ")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn three_elephants() {
+        let input = ring_of_valves();
+        let pressure = |minutes, agents, teaching| {
+            let problem = ValveProblem {
+                start: String::from("AA"),
+                minutes,
+                agents,
+                teaching,
+            };
+            problem.solve(&input).map(|plan| plan.pressure)
+        };
+
+        assert_eq!(
+            Network::new(&parse_input(&input), "AA").unwrap().flowing,
+            15
+        );
+        assert_eq!(pressure(40, 1, 0), Ok(3792));
+        assert_eq!(pressure(40, 2, 12), Ok(3208));
+        // 4 minutes teaching each elephant
+        assert_eq!(pressure(40, 4, 12), Ok(4028));
+    }
+
+    /// Most pressure `agents` agents release together, trying every way to pick disjoint sets of
+    /// valves from `sets`, in order so each choice is only tried once
+    fn best_disjoint(sets: &[(u64, usize)], agents: usize, from: usize, used: u64) -> usize {
+        if agents == 0 {
+            return 0;
+        }
+        sets[from..]
+            .iter()
+            .enumerate()
+            .filter(|(_, (set, _))| set & used == 0)
+            .map(|(idx, &(set, pressure))| {
+                pressure + best_disjoint(sets, agents - 1, from + idx, used | set)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn split_matches_every_disjoint_choice() {
+        let ring = ring_of_valves();
+        let cases = [(EXAMPLE, "AA", 26), (EXAMPLE, "DD", 20), (&ring, "AA", 14)];
+        for (input, start, minutes) in cases {
+            let network = Network::new(&parse_input(input), start).unwrap();
+            let mut best = HashMap::new();
+            every_route(&network, minutes, (network.start, 0, 0, 0), &mut best);
+            let sets: Vec<(u64, usize)> = best.into_iter().collect();
+
+            for agents in [2, 3] {
+                let problem = ValveProblem {
+                    start: String::from(start),
+                    minutes,
+                    agents,
+                    teaching: 0,
+                };
+                let expected = best_disjoint(&sets, agents, 0, 0);
+                assert_eq!(problem.solve(input).unwrap().pressure, expected);
+            }
+        }
+    }
+
     #[test]
     fn example_p1() {
         let result = Day16::part1(EXAMPLE);
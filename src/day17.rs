@@ -1,214 +1,602 @@
-use std::collections::{BTreeSet, HashMap};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 use crate::runner::Solve;
 
 pub struct Day17;
 
+/// The puzzle's rocks, in the order they fall
+pub const PUZZLE_ROCKS: &str = "####
+
+.#.
+###
+.#.
+
+..#
+..#
+###
+
+#
+#
+#
+#
+
+##
+##";
+
+/// Rows are stored as bitmasks, so chambers are at most this wide
+const MAX_WIDTH: i32 = 8;
+
+/// Deepest a snapshot of the surface reaches below the top of the tower, deeper surfaces aren't
+/// snapshotted
+const MAX_SNAPSHOT_DEPTH: usize = 64;
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-enum Tile {
-    Horizontal,
-    Cross,
-    LShape,
-    Vertical,
-    Square,
+pub enum RockError {
+    NoRocks,
+    /// Index of the rock, counted from 0
+    EmptyRock(usize),
+    UnexpectedChar(usize, char),
+    WidthOutOfRange(i32),
+    /// A rock doesn't fit between its spawn offset and the right wall
+    TooWide(usize),
 }
 
-use Tile::*;
+impl Display for RockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RockError::NoRocks => write!(f, "no rocks defined"),
+            RockError::EmptyRock(rock) => write!(f, "rock {} has no cells", rock),
+            RockError::UnexpectedChar(rock, ch) => {
+                write!(f, "unexpected {:?} in rock {}", ch, rock)
+            }
+            RockError::WidthOutOfRange(width) => {
+                write!(
+                    f,
+                    "chamber width {} is not between 1 and {}",
+                    width, MAX_WIDTH
+                )
+            }
+            RockError::TooWide(rock) => write!(f, "rock {} doesn't fit in the chamber", rock),
+        }
+    }
+}
 
-// y, x
-type Coordinate = (i32, i32);
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JetError {
+    /// The pattern has no jets to cycle through
+    NoJets,
+    UnexpectedChar(char),
+}
 
-impl Tile {
-    fn coords(&self) -> Vec<Coordinate> {
+impl Display for JetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Horizontal => vec![(0, 0), (0, 1), (0, 2), (0, 3)],
-            Cross => vec![(0, 1), (1, 0), (1, 1), (1, 2), (2, 1)],
-            LShape => vec![(0, 0), (0, 1), (0, 2), (1, 2), (2, 2)],
-            Vertical => vec![(0, 0), (1, 0), (2, 0), (3, 0)],
-            Square => vec![(0, 0), (0, 1), (1, 0), (1, 1)],
+            JetError::NoJets => write!(f, "no jets in the pattern"),
+            JetError::UnexpectedChar(ch) => write!(f, "unexpected {:?} in the jet pattern", ch),
         }
     }
+}
 
-    fn placed_at(&self, start: Coordinate) -> Vec<Coordinate> {
-        self.coords().into_iter().map(|c| add(c, start)).collect()
+impl From<JetError> for io::Error {
+    fn from(error: JetError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error.to_string())
     }
 }
 
-fn add(c1: Coordinate, c2: Coordinate) -> Coordinate {
-    (c1.0 + c2.0, c1.1 + c2.1)
+/// A rock as one bitmask per row, bottom row first, bit x set for a cell x columns from its left
+/// edge
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Rock {
+    rows: Vec<u8>,
+    width: i32,
 }
 
-#[derive(Clone, Copy, Eq, PartialEq, Debug)]
-enum Jet {
-    Left,
-    Right,
+impl Rock {
+    /// Rock drawn with `#` for its cells and `.` around them, top row first
+    fn parse(text: &str, index: usize) -> Result<Rock, RockError> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut cells = Vec::new();
+
+        for (row, line) in lines.iter().enumerate() {
+            for (x, ch) in line.chars().enumerate() {
+                match ch {
+                    '#' => cells.push((lines.len() - 1 - row, x)),
+                    '.' => (),
+                    ch => return Err(RockError::UnexpectedChar(index, ch)),
+                }
+            }
+        }
+
+        // trim empty rows and columns, so the rock starts at its bottom left corner
+        let bottom = cells
+            .iter()
+            .map(|c| c.0)
+            .min()
+            .ok_or(RockError::EmptyRock(index))?;
+        let left = cells.iter().map(|c| c.1).min().unwrap();
+        let top = cells.iter().map(|c| c.0).max().unwrap();
+        let width = cells.iter().map(|c| c.1).max().unwrap() - left + 1;
+        if width > MAX_WIDTH as usize {
+            return Err(RockError::TooWide(index));
+        }
+
+        let mut rows = vec![0; top - bottom + 1];
+        for (y, x) in cells {
+            rows[y - bottom] |= 1 << (x - left);
+        }
+
+        Ok(Rock {
+            rows,
+            width: width as i32,
+        })
+    }
 }
 
-impl Jet {
-    fn dir(&self) -> Coordinate {
+/// What the falling rock did in a step of the simulation
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Motion {
+    Appeared,
+    /// Index of the jet, its direction and whether the rock moved
+    Pushed(usize, Jet, bool),
+    Fell,
+    Settled,
+}
+
+impl Display for Motion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Jet::Left => (0, -1),
-            Jet::Right => (0, 1),
+            Motion::Appeared => write!(f, "appears"),
+            Motion::Pushed(jet, direction, moved) => {
+                let symbol = match direction {
+                    Jet::Left => '<',
+                    Jet::Right => '>',
+                };
+                let result = if *moved { "pushed" } else { "blocked" };
+                write!(f, "jet {} {}, {}", jet, symbol, result)
+            }
+            Motion::Fell => write!(f, "falls"),
+            Motion::Settled => write!(f, "comes to rest"),
         }
     }
 }
 
-fn within_bounds(coordinate: Coordinate) -> bool {
-    coordinate.0 >= 0 && coordinate.1 >= 0 && coordinate.1 < 7
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Cell {
+    Air,
+    Rock,
+    Falling,
+    Wall,
 }
 
-fn can_move_dir(rock_coords: &[Coordinate], dir: Coordinate, set: &BTreeSet<Coordinate>) -> bool {
-    rock_coords
-        .iter()
-        .map(|&c| add(c, dir))
-        .all(|c| within_bounds(c) && !set.contains(&c))
+/// The chamber during a step: the settled tower, and the falling rock with its bottom left corner
+/// at `position`
+struct Frame<'a> {
+    tower: &'a [u8],
+    rock: &'a Rock,
+    position: (usize, i32),
+    motion: Motion,
 }
 
-fn snapshot_line(set: &BTreeSet<Coordinate>, y: i32) -> u8 {
-    let mut result = 0;
-
-    if y < 0 {
-        return 0x7F;
+impl Frame<'_> {
+    /// Amount of rows up to the top of the rock where it appeared, so the view stays put while
+    /// it falls
+    fn height(&self, chamber: &Chamber) -> usize {
+        self.tower.len() + chamber.spawn_above as usize + self.rock.rows.len()
     }
 
-    for x in 0..7 {
-        if set.contains(&(y, x)) {
-            result += 1 << x;
+    /// Cell `x` of row `y`, rows below the floor are walls
+    fn cell(&self, y: i64, x: i32, chamber: &Chamber) -> Cell {
+        if y < 0 || x < 0 || x >= chamber.width {
+            return Cell::Wall;
         }
-    }
 
-    result
-}
+        let (rock_y, rock_x) = self.position;
+        let falling = (y as usize)
+            .checked_sub(rock_y)
+            .and_then(|row| self.rock.rows.get(row))
+            .is_some_and(|&mask| x >= rock_x && mask >> (x - rock_x) & 1 == 1);
+        let settled = self
+            .tower
+            .get(y as usize)
+            .is_some_and(|&row| row >> x & 1 == 1);
+
+        match (falling, self.motion) {
+            (true, Motion::Settled) => Cell::Rock,
+            (true, _) => Cell::Falling,
+            (false, _) if settled => Cell::Rock,
+            _ => Cell::Air,
+        }
+    }
 
-fn can_passthrough(set: &BTreeSet<Coordinate>, y: i32) -> bool {
-    let start = (y + 1, 0);
-    let mut seen = BTreeSet::new();
+    /// Top `rows` rows like the puzzle draws them, with the floor once it's in sight
+    fn render(&self, chamber: &Chamber, rows: usize) -> String {
+        let mut result = String::new();
+        let height = self.height(chamber) as i64;
+
+        for y in (height - rows as i64..height).rev().filter(|&y| y >= 0) {
+            result.push('|');
+            for x in 0..chamber.width {
+                result.push(match self.cell(y, x, chamber) {
+                    Cell::Rock => '#',
+                    Cell::Falling => '@',
+                    _ => '.',
+                });
+            }
+            result += "|\n";
+        }
+        if height <= rows as i64 {
+            result += &format!("+{}+\n", "-".repeat(chamber.width as usize));
+        }
 
-    let mut queue = vec![start];
+        result
+    }
 
-    while let Some(current) = queue.pop() {
-        if current.0 < y - 3 {
-            return true;
-        }
-        for dir in [(0, 1), (0, -1), (1, 0), (-1, 0)] {
-            let neighbour = add(current, dir);
-            if neighbour.0 <= y + 1
-                && within_bounds(neighbour)
-                && !set.contains(&neighbour)
-                && seen.insert(neighbour)
-            {
-                queue.push(neighbour);
+    /// Top `rows` rows as a binary PGM with a pixel per cell, walls and floor included
+    fn write_pgm(&self, path: &Path, chamber: &Chamber, rows: usize) -> io::Result<()> {
+        let height = self.height(chamber) as i64;
+        let mut image = format!("P5\n{} {}\n255\n", chamber.width + 2, rows).into_bytes();
+
+        for y in (height - rows as i64..height).rev() {
+            for x in -1..=chamber.width {
+                image.push(match self.cell(y, x, chamber) {
+                    Cell::Air => 0,
+                    Cell::Wall => 90,
+                    Cell::Rock => 170,
+                    Cell::Falling => 255,
+                });
             }
         }
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&image)?;
+        file.flush()
     }
-    false
 }
 
-fn try_snapshot(set: &BTreeSet<Coordinate>, y: i32) -> Option<Vec<u8>> {
-    let result = vec![
-        snapshot_line(set, y - 3),
-        snapshot_line(set, y - 2),
-        snapshot_line(set, y - 1),
-        snapshot_line(set, y),
-    ];
+/// Rocks separated by blank lines, in the order they fall
+fn parse_rocks(text: &str) -> Result<Vec<Rock>, RockError> {
+    let rocks: Vec<Rock> = text
+        .trim()
+        .replace("\r\n", "\n")
+        .split("\n\n")
+        .enumerate()
+        .map(|(index, rock)| Rock::parse(rock.trim(), index))
+        .collect::<Result<_, _>>()?;
 
-    // snapshot is not useful unless it blocks off all pieces
-    if !can_passthrough(set, y) {
-        Some(result)
-    } else {
-        None
+    if rocks.is_empty() {
+        return Err(RockError::NoRocks);
     }
+    Ok(rocks)
 }
 
-fn height_after(rock_amount: usize, jets: Vec<Jet>) -> usize {
-    let mut set: BTreeSet<(i32, i32)> = BTreeSet::new();
-
-    let mut jets = jets.into_iter().enumerate().cycle().peekable();
-
-    let mut snapshots = HashMap::new();
-    let mut history = Vec::new();
+/// The falling rocks and where they appear: `spawn_left` cells from the left wall and
+/// `spawn_above` empty rows above the highest rock
+#[derive(Debug, Clone)]
+pub struct Chamber {
+    rocks: Vec<Rock>,
+    width: i32,
+    spawn_left: i32,
+    spawn_above: i32,
+}
 
-    for (current_rock, (rock_idx, rock)) in ORDER
-        .iter()
-        .enumerate()
-        .cycle()
-        .take(rock_amount)
-        .enumerate()
-    {
-        let current_height = set.last().map(|c| c.0).unwrap_or(-1);
-        history.push(current_height);
+impl Chamber {
+    pub fn new(
+        rocks: &str,
+        width: i32,
+        spawn_left: i32,
+        spawn_above: i32,
+    ) -> Result<Chamber, RockError> {
+        if !(1..=MAX_WIDTH).contains(&width) {
+            return Err(RockError::WidthOutOfRange(width));
+        }
 
-        if let Some(snap) = try_snapshot(&set, current_height) {
-            let jet_idx = jets.peek().unwrap().0;
-            let key = (snap, rock_idx, jet_idx);
-            if let Some(&(cycle_start_height, cycle_start_rocks)) = snapshots.get(&key) {
-                let cycle_height = (current_height - cycle_start_height) as usize;
-                let cycle_rocks = current_rock - cycle_start_rocks;
+        let rocks = parse_rocks(rocks)?;
+        if let Some(rock) = rocks
+            .iter()
+            .position(|rock| spawn_left < 0 || spawn_left + rock.width > width)
+        {
+            return Err(RockError::TooWide(rock));
+        }
 
-                let amount_required = rock_amount - cycle_start_rocks;
+        Ok(Chamber {
+            rocks,
+            width,
+            spawn_left,
+            spawn_above: spawn_above.max(0),
+        })
+    }
 
-                let cycle_count = amount_required / cycle_rocks;
-                let remaining_rocks = amount_required % cycle_rocks;
+    fn puzzle() -> Chamber {
+        Chamber::new(PUZZLE_ROCKS, 7, 2, 3).unwrap()
+    }
 
-                let total_by_cycle = cycle_height * cycle_count;
+    fn full_row(&self) -> u8 {
+        ((1u16 << self.width) - 1) as u8
+    }
 
-                let remaining_height = history[cycle_start_rocks + remaining_rocks] as usize
-                    - history[cycle_start_rocks] as usize;
+    /// Whether the rock with its bottom left corner at `(y, x)` overlaps a wall or settled rock
+    fn collides(&self, tower: &[u8], rock: &Rock, y: usize, x: i32) -> bool {
+        x < 0
+            || x + rock.width > self.width
+            || rock
+                .rows
+                .iter()
+                .zip(tower.iter().skip(y))
+                .any(|(&mask, &row)| row & (mask << x) != 0)
+    }
 
-                return total_by_cycle + remaining_height + cycle_start_height as usize + 1;
+    /// The free cells a falling rock could still reach, one row each from the top of the tower
+    /// down, or `None` while they reach too deep.
+    ///
+    /// Rocks settled below those cells can't affect any rock to come, so the chamber repeats
+    /// once this snapshot, the next rock and the next jet all repeat.
+    fn try_snapshot(&self, tower: &[u8]) -> Option<Vec<u8>> {
+        let full = self.full_row();
+        let rows = tower.len().min(MAX_SNAPSHOT_DEPTH + 1);
+        let free: Vec<u8> = tower
+            .iter()
+            .rev()
+            .take(rows)
+            .map(|row| full & !row)
+            .collect();
+
+        // flood fill from the empty row above the tower, a row at a time until nothing changes
+        let mut reachable = vec![0u8; rows];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for idx in 0..rows {
+                let above = if idx == 0 { full } else { reachable[idx - 1] };
+                let below = reachable.get(idx + 1).copied().unwrap_or(0);
+
+                let mut row = (reachable[idx] | above | below) & free[idx];
+                loop {
+                    let spread = (row | row << 1 | row >> 1) & free[idx];
+                    if spread == row {
+                        break;
+                    }
+                    row = spread;
+                }
+
+                if row != reachable[idx] {
+                    reachable[idx] = row;
+                    changed = true;
+                }
             }
+        }
 
-            snapshots.insert(key, (current_height, current_rock));
+        let depth = reachable
+            .iter()
+            .rposition(|&row| row != 0)
+            .map_or(0, |idx| idx + 1);
+        if depth > MAX_SNAPSHOT_DEPTH {
+            return None;
         }
+        reachable.truncate(depth);
+        Some(reachable)
+    }
 
-        let rock_y = current_height + 4;
-        let rock_x = 2;
+    /// Lets the rock fall from its spawn until it settles, pushed by the jets from `jet_idx` on.
+    ///
+    /// `on_step` sees the chamber after every step, before the rock settles into the tower.
+    fn drop_rock(
+        &self,
+        tower: &mut Vec<u8>,
+        rock: &Rock,
+        jets: &[Jet],
+        jet_idx: &mut usize,
+        mut on_step: impl FnMut(&Frame),
+    ) {
+        let mut y = tower.len() + self.spawn_above as usize;
+        let mut x = self.spawn_left;
+        let mut step = |motion, y, x| {
+            on_step(&Frame {
+                tower,
+                rock,
+                position: (y, x),
+                motion,
+            })
+        };
+        step(Motion::Appeared, y, x);
 
-        let mut rock_coords = rock.placed_at((rock_y, rock_x));
         loop {
-            let jet = jets.next().unwrap().1;
-            let dir = jet.dir();
-
-            if can_move_dir(&rock_coords, dir, &set) {
-                rock_coords.iter_mut().for_each(|c| *c = add(*c, dir));
+            let jet = *jet_idx;
+            let pushed = x + jets[jet].dx();
+            *jet_idx = (*jet_idx + 1) % jets.len();
+            let moved = !self.collides(tower, rock, y, pushed);
+            if moved {
+                x = pushed;
             }
+            step(Motion::Pushed(jet, jets[jet], moved), y, x);
 
-            if can_move_dir(&rock_coords, (-1, 0), &set) {
-                rock_coords.iter_mut().for_each(|c| *c = add(*c, (-1, 0)));
-            } else {
+            if y == 0 || self.collides(tower, rock, y - 1, x) {
                 break;
             }
+            y -= 1;
+            step(Motion::Fell, y, x);
         }
+        step(Motion::Settled, y, x);
 
-        set.extend(rock_coords);
+        if tower.len() < y + rock.rows.len() {
+            tower.resize(y + rock.rows.len(), 0);
+        }
+        for (row, mask) in tower[y..].iter_mut().zip(rock.rows.iter()) {
+            *row |= mask << x;
+        }
     }
 
-    set.last().unwrap().0 as usize + 1
+    fn height_after(&self, rock_amount: usize, jets: &[Jet]) -> usize {
+        let mut tower: Vec<u8> = Vec::new();
+        let mut jet_idx = 0;
+
+        let mut snapshots = HashMap::new();
+        let mut history = Vec::new();
+
+        for (current_rock, (rock_idx, rock)) in self
+            .rocks
+            .iter()
+            .enumerate()
+            .cycle()
+            .take(rock_amount)
+            .enumerate()
+        {
+            let current_height = tower.len();
+            history.push(current_height);
+
+            if let Some(snap) = self.try_snapshot(&tower) {
+                let key = (snap, rock_idx, jet_idx);
+                if let Some(&(cycle_start_height, cycle_start_rocks)) = snapshots.get(&key) {
+                    let cycle_height = current_height - cycle_start_height;
+                    let cycle_rocks = current_rock - cycle_start_rocks;
+
+                    let amount_required = rock_amount - cycle_start_rocks;
+
+                    let cycle_count = amount_required / cycle_rocks;
+                    let remaining_rocks = amount_required % cycle_rocks;
+
+                    let total_by_cycle = cycle_height * cycle_count;
+
+                    let remaining_height =
+                        history[cycle_start_rocks + remaining_rocks] - history[cycle_start_rocks];
+
+                    return total_by_cycle + remaining_height + cycle_start_height;
+                }
+
+                snapshots.insert(key, (current_height, current_rock));
+            }
+
+            self.drop_rock(&mut tower, rock, jets, &mut jet_idx, |_| {});
+        }
+
+        tower.len()
+    }
 }
 
-fn parse_jet(c: char) -> Jet {
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum Jet {
+    Left,
+    Right,
+}
+
+impl Jet {
+    fn dx(&self) -> i32 {
+        match self {
+            Jet::Left => -1,
+            Jet::Right => 1,
+        }
+    }
+}
+
+fn parse_jet(c: char) -> Result<Jet, JetError> {
     match c {
-        '<' => Jet::Left,
-        '>' => Jet::Right,
-        _ => panic!("Unexpected jet char"),
+        '<' => Ok(Jet::Left),
+        '>' => Ok(Jet::Right),
+        _ => Err(JetError::UnexpectedChar(c)),
     }
 }
 
-fn parse_input(input: &str) -> Vec<Jet> {
-    input.trim().chars().map(parse_jet).collect()
+/// Jets of the pattern, which has at least one so it can be cycled through
+fn parse_input(input: &str) -> Result<Vec<Jet>, JetError> {
+    let jets = input
+        .trim()
+        .chars()
+        .map(parse_jet)
+        .collect::<Result<Vec<Jet>, JetError>>()?;
+    if jets.is_empty() {
+        return Err(JetError::NoJets);
+    }
+    Ok(jets)
 }
 
-const ORDER: [Tile; 5] = [Horizontal, Cross, LShape, Vertical, Square];
+/// Height of the tower after `rocks` rocks fall in the chamber
+pub fn height(input: &str, chamber: &Chamber, rocks: usize) -> Result<usize, JetError> {
+    Ok(chamber.height_after(rocks, &parse_input(input)?))
+}
+
+impl Chamber {
+    /// Drops `rocks` rocks one by one without looking for cycles, passing every step to
+    /// `on_frame` along with the number of the rock and of the frame, until it fails.
+    fn replay(
+        &self,
+        jets: &[Jet],
+        rocks: usize,
+        mut on_frame: impl FnMut(&Frame, usize, usize) -> io::Result<()>,
+    ) -> io::Result<usize> {
+        let mut tower = Vec::new();
+        let mut jet_idx = 0;
+        let mut frames = 0;
+        let mut result = Ok(());
+
+        for (number, rock) in self.rocks.iter().cycle().take(rocks).enumerate() {
+            self.drop_rock(&mut tower, rock, jets, &mut jet_idx, |frame| {
+                if result.is_ok() {
+                    result = on_frame(frame, number + 1, frames);
+                    frames += 1;
+                }
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+
+        result.map(|_| frames)
+    }
+}
+
+/// Writes every step of the first `rocks` rocks as text showing the top `rows` rows. With a
+/// delay, the terminal is cleared before each step, turning it into an animation.
+pub fn replay(
+    input: &str,
+    chamber: &Chamber,
+    rocks: usize,
+    rows: usize,
+    delay: Option<Duration>,
+    out: &mut impl Write,
+) -> io::Result<usize> {
+    chamber.replay(&parse_input(input)?, rocks, |frame, number, _| {
+        if let Some(delay) = delay {
+            thread::sleep(delay);
+            // clear the screen and move the cursor back to the top left
+            write!(out, "\x1b[2J\x1b[H")?;
+        }
+        writeln!(out, "rock {}: {}", number, frame.motion)?;
+        writeln!(out, "{}", frame.render(chamber, rows))?;
+        out.flush()
+    })
+}
+
+/// Writes every step of the first `rocks` rocks as a PGM image of the top `rows` rows, to
+/// stitch into an animation
+pub fn write_frames(
+    input: &str,
+    chamber: &Chamber,
+    rocks: usize,
+    rows: usize,
+    directory: &Path,
+) -> io::Result<usize> {
+    fs::create_dir_all(directory)?;
+
+    chamber.replay(&parse_input(input)?, rocks, |frame, _, idx| {
+        let path = directory.join(format!("frame_{:06}.pgm", idx));
+        frame.write_pgm(&path, chamber, rows)
+    })
+}
 
 impl Solve<usize, usize> for Day17 {
     fn part1(input: &str) -> usize {
-        let jets = parse_input(input);
-        height_after(2022, jets)
+        let jets = parse_input(input).unwrap();
+        Chamber::puzzle().height_after(2022, &jets)
     }
     fn part2(input: &str) -> usize {
-        let jets = parse_input(input);
+        let jets = parse_input(input).unwrap();
 
-        height_after(1000000000000, jets)
+        Chamber::puzzle().height_after(1000000000000, &jets)
     }
 }
 
@@ -218,6 +606,127 @@ mod tests {
 
     const EXAMPLE: &str = r#">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>"#;
 
+    #[test]
+    fn parses_rocks() {
+        let rocks = parse_rocks(PUZZLE_ROCKS).unwrap();
+
+        assert_eq!(rocks.len(), 5);
+        assert_eq!(rocks[0].rows, vec![0b1111]);
+        assert_eq!(rocks[1].rows, vec![0b010, 0b111, 0b010]);
+        assert_eq!(rocks[2].rows, vec![0b111, 0b100, 0b100]);
+        assert_eq!(rocks[3].width, 1);
+        assert_eq!(
+            parse_rocks("\n...\n.##.\n\n")
+                .unwrap()
+                .iter()
+                .map(|rock| (rock.rows.clone(), rock.width))
+                .collect::<Vec<_>>(),
+            vec![(vec![0b11], 2)]
+        );
+
+        assert_eq!(parse_rocks("#x"), Err(RockError::UnexpectedChar(0, 'x')));
+        assert_eq!(parse_rocks("#\n\n.."), Err(RockError::EmptyRock(1)));
+        assert_eq!(parse_rocks("#########"), Err(RockError::TooWide(0)));
+        assert_eq!(
+            Chamber::new(PUZZLE_ROCKS, 5, 2, 3).unwrap_err(),
+            RockError::TooWide(0)
+        );
+        assert_eq!(
+            Chamber::new(PUZZLE_ROCKS, 9, 2, 3).unwrap_err(),
+            RockError::WidthOutOfRange(9)
+        );
+    }
+
+    #[test]
+    fn cycles_match_simulation() {
+        let chambers = [
+            Chamber::puzzle(),
+            Chamber::new(PUZZLE_ROCKS, 8, 1, 2).unwrap(),
+            Chamber::new("#\n\n##\n\n.#\n##", 4, 0, 3).unwrap(),
+            Chamber::new("###\n#.#", 5, 1, 1).unwrap(),
+        ];
+
+        for chamber in chambers.iter() {
+            let jets = parse_input(EXAMPLE).unwrap();
+            let mut tower = Vec::new();
+            let mut jet_idx = 0;
+            for (rocks, rock) in chamber.rocks.iter().cycle().take(3000).enumerate() {
+                if rocks % 250 == 0 {
+                    assert_eq!(chamber.height_after(rocks, &jets), tower.len());
+                }
+                chamber.drop_rock(&mut tower, rock, &jets, &mut jet_idx, |_| {});
+            }
+        }
+    }
+
+    #[test]
+    fn snapshots_reachable_cells() {
+        let chamber = Chamber::puzzle();
+        // the pocket under the overhang on the right is reachable from the left, the hole on the
+        // left is covered
+        let tower = [0b1111111, 0b0111110, 0b0000011, 0b0011111];
+
+        assert_eq!(
+            chamber.try_snapshot(&tower),
+            Some(vec![0b1100000, 0b1111100, 0b1000000])
+        );
+        assert_eq!(chamber.try_snapshot(&[]), Some(vec![]));
+        assert_eq!(chamber.try_snapshot(&[0; 100]), None);
+    }
+
+    #[test]
+    fn replays_first_rock() {
+        let mut out = Vec::new();
+        let frames = replay(EXAMPLE, &Chamber::puzzle(), 1, 10, None, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        // appears, then 4 pushes and 3 falls before it comes to rest
+        assert_eq!(frames, 9);
+        assert!(out.starts_with(
+            "rock 1: appears\n|..@@@@.|\n|.......|\n|.......|\n|.......|\n+-------+\n\n"
+        ));
+        assert!(out.contains("rock 1: jet 3 <, pushed\n"));
+        assert!(out.ends_with(
+            "rock 1: comes to rest\n|.......|\n|.......|\n|.......|\n|..####.|\n+-------+\n\n"
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_jets() {
+        let chamber = Chamber::puzzle();
+
+        assert_eq!(parse_input(""), Err(JetError::NoJets));
+        assert_eq!(parse_input(" \n"), Err(JetError::NoJets));
+        assert_eq!(parse_input("<>x"), Err(JetError::UnexpectedChar('x')));
+        assert_eq!(height("\n", &chamber, 5), Err(JetError::NoJets));
+        assert!(replay("", &chamber, 1, 10, None, &mut Vec::new()).is_err());
+    }
+
+    /// Run with `cargo test --release -- --ignored bench_part1`
+    #[test]
+    #[ignore]
+    fn bench_part1() {
+        use std::time::Instant;
+
+        let jets = parse_input(EXAMPLE).unwrap();
+        let chamber = Chamber::puzzle();
+
+        let start = Instant::now();
+        let mut tower = Vec::new();
+        let mut jet_idx = 0;
+        for rock in chamber.rocks.iter().cycle().take(2022) {
+            chamber.drop_rock(&mut tower, rock, &jets, &mut jet_idx, |_| {});
+        }
+        let simulated = start.elapsed();
+
+        assert_eq!(tower.len(), 3068);
+        assert!(
+            simulated < Duration::from_millis(1),
+            "2022 rocks took {:?}",
+            simulated
+        );
+    }
+
     #[test]
     fn example_p1() {
         let result = Day17::part1(EXAMPLE);
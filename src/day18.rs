@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
 
 use crate::runner::Solve;
 
@@ -9,7 +10,11 @@ fn parse_line(input: &str) -> Vec<i32> {
 }
 
 fn parse_input(input: &str) -> HashSet<Vec<i32>> {
-    input.lines().map(parse_line).collect()
+    input
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_line)
+        .collect()
 }
 
 fn neighbours(position: &[i32]) -> Vec<Vec<i32>> {
@@ -31,7 +36,7 @@ fn count_adjacent(position: &[i32], cubes: &HashSet<Vec<i32>>) -> usize {
         .count()
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
 struct Bounds {
     x_min: i32,
     x_max: i32,
@@ -43,87 +48,346 @@ struct Bounds {
     z_max: i32,
 }
 
-fn within_bounds(position: &[i32], bounds: &Bounds) -> bool {
-    position[0] >= bounds.x_min
-        && position[0] <= bounds.x_max
-        && position[1] >= bounds.y_min
-        && position[1] <= bounds.y_max
-        && position[2] >= bounds.z_min
-        && position[2] <= bounds.z_max
-}
+impl Bounds {
+    /// Smallest box holding every cell, `None` without any
+    fn around<'a>(cells: impl IntoIterator<Item = &'a Vec<i32>>) -> Option<Bounds> {
+        cells.into_iter().fold(None, |bounds, c| {
+            Some(match bounds {
+                None => Bounds {
+                    x_min: c[0],
+                    x_max: c[0],
+                    y_min: c[1],
+                    y_max: c[1],
+                    z_min: c[2],
+                    z_max: c[2],
+                },
+                Some(b) => Bounds {
+                    x_min: b.x_min.min(c[0]),
+                    x_max: b.x_max.max(c[0]),
+                    y_min: b.y_min.min(c[1]),
+                    y_max: b.y_max.max(c[1]),
+                    z_min: b.z_min.min(c[2]),
+                    z_max: b.z_max.max(c[2]),
+                },
+            })
+        })
+    }
 
-fn inner_cubes(cubes: &HashSet<Vec<i32>>) -> HashSet<Vec<i32>> {
-    let x_min = cubes.iter().map(|c| c[0]).min().unwrap() - 1;
-    let x_max = cubes.iter().map(|c| c[0]).max().unwrap() + 1;
+    fn expanded(&self, by: i32) -> Bounds {
+        Bounds {
+            x_min: self.x_min - by,
+            x_max: self.x_max + by,
+            y_min: self.y_min - by,
+            y_max: self.y_max + by,
+            z_min: self.z_min - by,
+            z_max: self.z_max + by,
+        }
+    }
 
-    let y_min = cubes.iter().map(|c| c[1]).min().unwrap() - 1;
-    let y_max = cubes.iter().map(|c| c[1]).max().unwrap() + 1;
+    fn contains(&self, position: &[i32]) -> bool {
+        position[0] >= self.x_min
+            && position[0] <= self.x_max
+            && position[1] >= self.y_min
+            && position[1] <= self.y_max
+            && position[2] >= self.z_min
+            && position[2] <= self.z_max
+    }
+}
 
-    let z_min = cubes.iter().map(|c| c[2]).min().unwrap() - 1;
-    let z_max = cubes.iter().map(|c| c[2]).max().unwrap() + 1;
-    let bounds = Bounds {
-        x_min,
-        x_max,
-        y_min,
-        y_max,
-        z_min,
-        z_max,
-    };
+impl Display for Bounds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "x {}..{}, y {}..{}, z {}..{}",
+            self.x_min, self.x_max, self.y_min, self.y_max, self.z_min, self.z_max
+        )
+    }
+}
 
-    let mut inside_positions = HashSet::new();
+/// Cells connected to `start` through cells `passable` accepts, `start` included
+fn flood_fill(start: Vec<i32>, passable: impl Fn(&[i32]) -> bool) -> HashSet<Vec<i32>> {
+    let mut visit = vec![start.clone()];
     let mut seen = HashSet::new();
+    seen.insert(start);
+
+    while let Some(current) = visit.pop() {
+        for neighbour in neighbours(&current) {
+            if passable(&neighbour) && seen.insert(neighbour.clone()) {
+                visit.push(neighbour);
+            }
+        }
+    }
+
+    seen
+}
+
+/// Air enclosed by the lava, unreachable from outside
+#[derive(Debug, Clone)]
+struct Pocket {
+    cells: HashSet<Vec<i32>>,
+    /// Faces of the pocket's cells touching lava, which is all of them that don't touch another
+    /// cell of the pocket
+    surface_area: usize,
+    bounds: Bounds,
+}
+
+impl Pocket {
+    fn volume(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+/// The lava cubes and the air around them, found with a single flood fill from a box one cell
+/// bigger than the droplet
+struct Droplet {
+    cubes: HashSet<Vec<i32>>,
+    bounds: Bounds,
+    outside: HashSet<Vec<i32>>,
+}
+
+impl Droplet {
+    fn new(cubes: HashSet<Vec<i32>>) -> Droplet {
+        // without any cubes the box around the origin is all outside air
+        let bounds = Bounds::around(&cubes).unwrap_or_default().expanded(1);
+        // the box's corner is never lava
+        let corner = vec![bounds.x_min, bounds.y_min, bounds.z_min];
+        let outside = flood_fill(corner, |position| {
+            bounds.contains(position) && !cubes.contains(position)
+        });
+
+        Droplet {
+            cubes,
+            bounds,
+            outside,
+        }
+    }
 
-    for x in x_min..=x_max {
-        for y in y_min..=y_max {
-            for z in z_min..=z_max {
-                let start = vec![x, y, z];
-                if !seen.insert(start.clone()) {
-                    continue;
+    /// Faces of the lava touching air, pockets included
+    fn surface_area(&self) -> usize {
+        self.cubes
+            .iter()
+            .map(|cube| count_adjacent(cube, &self.cubes))
+            .sum()
+    }
+
+    /// Faces of the lava touching the air outside
+    fn exterior_surface_area(&self) -> usize {
+        self.cubes
+            .iter()
+            .flat_map(|cube| neighbours(cube))
+            .filter(|neighbour| self.outside.contains(neighbour))
+            .count()
+    }
+
+    /// Every enclosed pocket, ordered by their lowest corner
+    fn pockets(&self) -> Vec<Pocket> {
+        let mut pockets: Vec<Pocket> = Vec::new();
+        let mut enclosed = HashSet::new();
+
+        for x in self.bounds.x_min..=self.bounds.x_max {
+            for y in self.bounds.y_min..=self.bounds.y_max {
+                for z in self.bounds.z_min..=self.bounds.z_max {
+                    let start = vec![x, y, z];
+                    if self.cubes.contains(&start)
+                        || self.outside.contains(&start)
+                        || enclosed.contains(&start)
+                    {
+                        continue;
+                    }
+
+                    // pockets are surrounded by lava, so the fill never leaves the box
+                    let cells = flood_fill(start, |position| !self.cubes.contains(position));
+                    enclosed.extend(cells.iter().cloned());
+                    pockets.push(Pocket {
+                        surface_area: cells.iter().map(|cell| count_adjacent(cell, &cells)).sum(),
+                        bounds: Bounds::around(&cells).unwrap(),
+                        cells,
+                    });
                 }
-                let mut outside = false;
-                let mut island = HashSet::new();
-                let mut visit = vec![start.clone()];
-                island.insert(start.clone());
-
-                let kind = cubes.contains(&start);
-
-                while let Some(current) = visit.pop() {
-                    for neighbour in neighbours(&current) {
-                        if kind == cubes.contains(&neighbour) && seen.insert(neighbour.clone()) {
-                            if within_bounds(&neighbour, &bounds) {
-                                island.insert(neighbour.clone());
-                                visit.push(neighbour);
-                            } else {
-                                outside = true;
-                            }
-                        }
+            }
+        }
+
+        pockets
+    }
+
+    /// The lava with its pockets filled in
+    fn filled(&self) -> HashSet<Vec<i32>> {
+        let mut result = self.cubes.clone();
+        for pocket in self.pockets() {
+            result.extend(pocket.cells);
+        }
+        result
+    }
+}
+
+/// Square face of a cell, its corners listed counter-clockwise seen from the side `normal` points
+/// to
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct Face {
+    corners: [usize; 4],
+    normal: [i32; 3],
+}
+
+/// Faces sharing their corners, grouped in named objects
+#[derive(Debug, Default)]
+struct Mesh {
+    vertices: Vec<[i32; 3]>,
+    vertex_ids: HashMap<[i32; 3], usize>,
+    objects: Vec<(String, Vec<Face>)>,
+}
+
+impl Mesh {
+    fn vertex(&mut self, position: [i32; 3]) -> usize {
+        let next_id = self.vertices.len();
+        let id = *self.vertex_ids.entry(position).or_insert(next_id);
+        if id == next_id {
+            self.vertices.push(position);
+        }
+        id
+    }
+
+    /// Adds the faces of `cells` that don't touch another one of them, facing away from them
+    fn add_surface(&mut self, name: &str, cells: &HashSet<Vec<i32>>) {
+        let mut faces = Vec::new();
+        // sorted so the same droplet always gives the same file
+        let mut sorted: Vec<&Vec<i32>> = cells.iter().collect();
+        sorted.sort();
+
+        for cell in sorted {
+            for axis in 0..3 {
+                for direction in [-1, 1] {
+                    let mut neighbour = cell.clone();
+                    neighbour[axis] += direction;
+                    if cells.contains(&neighbour) {
+                        continue;
                     }
+
+                    // the other two axes in cyclic order, so u × v points along `axis`
+                    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+                    let mut base = [cell[0], cell[1], cell[2]];
+                    if direction > 0 {
+                        base[axis] += 1;
+                    }
+                    let offset = |du: i32, dv: i32| {
+                        let mut corner = base;
+                        corner[u] += du;
+                        corner[v] += dv;
+                        corner
+                    };
+
+                    let mut corners = [offset(0, 0), offset(1, 0), offset(1, 1), offset(0, 1)];
+                    if direction < 0 {
+                        corners.reverse();
+                    }
+                    let mut normal = [0; 3];
+                    normal[axis] = direction;
+
+                    faces.push(Face {
+                        corners: corners.map(|corner| self.vertex(corner)),
+                        normal,
+                    });
                 }
+            }
+        }
+
+        self.objects.push((String::from(name), faces));
+    }
+
+    /// Wavefront OBJ, one object per surface with quads over a shared vertex list
+    fn to_obj(&self) -> String {
+        let mut result = String::new();
+
+        for [x, y, z] in self.vertices.iter() {
+            result += &format!("v {} {} {}\n", x, y, z);
+        }
+        for (name, faces) in self.objects.iter() {
+            result += &format!("o {}\n", name);
+            for face in faces {
+                // OBJ counts vertices from 1
+                let [a, b, c, d] = face.corners.map(|id| id + 1);
+                result += &format!("f {} {} {} {}\n", a, b, c, d);
+            }
+        }
+
+        result
+    }
+
+    /// ASCII STL, one solid per surface with every face split in two triangles
+    fn to_stl(&self) -> String {
+        let mut result = String::new();
 
-                if !outside {
-                    inside_positions.extend(island);
+        for (name, faces) in self.objects.iter() {
+            result += &format!("solid {}\n", name);
+            for face in faces {
+                let [nx, ny, nz] = face.normal;
+                let [a, b, c, d] = face.corners;
+                for triangle in [[a, b, c], [a, c, d]] {
+                    result += &format!("  facet normal {} {} {}\n    outer loop\n", nx, ny, nz);
+                    for id in triangle {
+                        let [x, y, z] = self.vertices[id];
+                        result += &format!("      vertex {} {} {}\n", x, y, z);
+                    }
+                    result += "    endloop\n  endfacet\n";
                 }
             }
+            result += &format!("endsolid {}\n", name);
         }
+
+        result
+    }
+}
+
+/// The droplet's exterior surface, with the air pockets trapped inside as a second object, as an
+/// OBJ or (if `stl`) an ASCII STL mesh
+pub fn mesh(input: &str, stl: bool) -> String {
+    let droplet = Droplet::new(parse_input(input));
+    let filled = droplet.filled();
+    let pockets: HashSet<Vec<i32>> = filled.difference(&droplet.cubes).cloned().collect();
+
+    let mut mesh = Mesh::default();
+    mesh.add_surface("exterior", &filled);
+    mesh.add_surface("pockets", &pockets);
+
+    if stl {
+        mesh.to_stl()
+    } else {
+        mesh.to_obj()
     }
+}
+
+/// Volume, surface area and bounding box of every air pocket inside the droplet
+pub fn pockets(input: &str) -> String {
+    let droplet = Droplet::new(parse_input(input));
+    let pockets = droplet.pockets();
 
-    inside_positions
+    let mut result = String::new();
+    for (idx, pocket) in pockets.iter().enumerate() {
+        result += &format!(
+            "pocket {}: volume {}, surface area {}, {}\n",
+            idx + 1,
+            pocket.volume(),
+            pocket.surface_area,
+            pocket.bounds
+        );
+    }
+    let plural = if pockets.len() == 1 { "" } else { "s" };
+    result += &format!(
+        "{} pocket{}, surface area {} of which {} exterior\n",
+        pockets.len(),
+        plural,
+        droplet.surface_area(),
+        droplet.exterior_surface_area()
+    );
+
+    result
 }
 
 impl Solve<usize, usize> for Day18 {
     fn part1(input: &str) -> usize {
-        let cubes = parse_input(input);
-
-        cubes.iter().map(|cube| count_adjacent(cube, &cubes)).sum()
+        Droplet::new(parse_input(input)).surface_area()
     }
     fn part2(input: &str) -> usize {
-        let cubes = parse_input(input);
-        let inside_positions = inner_cubes(&cubes);
-
-        inside_positions
-            .iter()
-            .map(|cube| count_adjacent(cube, &inside_positions))
-            .sum()
+        Droplet::new(parse_input(input)).exterior_surface_area()
     }
 }
 
@@ -145,6 +409,100 @@ mod tests {
 2,1,5
 2,3,5"#;
 
+    #[test]
+    fn finds_pockets() {
+        let droplet = Droplet::new(parse_input(EXAMPLE));
+        let pockets = droplet.pockets();
+        assert_eq!(pockets.len(), 1);
+        assert_eq!(pockets[0].volume(), 1);
+        assert_eq!(pockets[0].surface_area, 6);
+        assert_eq!(pockets[0].bounds.to_string(), "x 2..2, y 2..2, z 5..5");
+
+        // a 7 by 5 by 5 box, hollow apart from a wall splitting it in two
+        let mut cubes = HashSet::new();
+        for x in 0..=6 {
+            for y in 0..=4 {
+                for z in 0..=4 {
+                    let shell = [y, z].iter().any(|&c| c == 0 || c == 4);
+                    if shell || x == 0 || x == 3 || x == 6 {
+                        cubes.insert(vec![x, y, z]);
+                    }
+                }
+            }
+        }
+        let droplet = Droplet::new(cubes);
+        let pockets = droplet.pockets();
+
+        assert_eq!(pockets.len(), 2);
+        for (pocket, bounds) in pockets.iter().zip(["x 1..2", "x 4..5"]) {
+            assert_eq!(pocket.volume(), 18);
+            assert_eq!(pocket.surface_area, 42);
+            assert_eq!(
+                pocket.bounds.to_string(),
+                format!("{}, y 1..3, z 1..3", bounds)
+            );
+        }
+        assert_eq!(droplet.exterior_surface_area(), 190);
+        assert_eq!(droplet.surface_area(), 190 + 2 * 42);
+    }
+
+    #[test]
+    fn mesh_example() {
+        let obj = mesh(EXAMPLE, false);
+        let exterior = obj.split("o pockets\n").next().unwrap();
+        let pockets = obj.split("o pockets\n").nth(1).unwrap();
+
+        assert_eq!(exterior.matches("\nf ").count(), 58);
+        assert_eq!(pockets.matches("f ").count(), 6);
+        // every corner is listed once, even where faces meet
+        let vertices: Vec<&str> = obj.lines().filter(|line| line.starts_with("v ")).collect();
+        let unique: HashSet<&str> = vertices.iter().copied().collect();
+        assert_eq!(vertices.len(), unique.len());
+
+        let stl = mesh(EXAMPLE, true);
+        assert!(stl.starts_with("solid exterior\n  facet normal "));
+        assert_eq!(stl.matches("facet normal").count(), 2 * (58 + 6));
+        assert!(stl.ends_with("endsolid pockets\n"));
+    }
+
+    #[test]
+    fn faces_point_outwards() {
+        let mut mesh = Mesh::default();
+        mesh.add_surface("cube", &[vec![0, 0, 0]].into_iter().collect());
+
+        let faces = &mesh.objects[0].1;
+        assert_eq!(faces.len(), 6);
+        assert_eq!(mesh.vertices.len(), 8);
+        for face in faces {
+            let [a, b, c, _] = face.corners.map(|id| mesh.vertices[id]);
+            let edge = |from: [i32; 3], to: [i32; 3]| [0, 1, 2].map(|idx| to[idx] - from[idx]);
+            let (u, v) = (edge(a, b), edge(b, c));
+            let cross = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            assert_eq!(cross, face.normal);
+        }
+    }
+
+    #[test]
+    fn empty_droplet() {
+        for input in ["", "\n"] {
+            assert_eq!(
+                pockets(input),
+                "0 pockets, surface area 0 of which 0 exterior\n"
+            );
+            assert_eq!(mesh(input, false), "o exterior\no pockets\n");
+            assert_eq!(
+                mesh(input, true),
+                "solid exterior\nendsolid exterior\nsolid pockets\nendsolid pockets\n"
+            );
+            assert_eq!(Day18::part1(input), 0);
+            assert_eq!(Day18::part2(input), 0);
+        }
+    }
+
     #[test]
     fn example_p1() {
         let result = Day18::part1(EXAMPLE);
@@ -1,236 +1,240 @@
 use std::{
-    collections::{BinaryHeap, HashSet},
+    fmt::{self, Display},
     ops::Add,
+    thread,
 };
 
-use crate::runner::Solve;
+use crate::runner::{SearchStats, Solve};
 
 pub struct Day19;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-struct ObsidianCost {
-    ore: usize,
-    clay: usize,
+/// Bags are fixed size arrays so states stay `Copy`, blueprints can't use more resources
+const MAX_RESOURCES: usize = 8;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BlueprintError {
+    /// Sentence that isn't "Each <resource> robot costs <amount> <resource> [and ..]"
+    InvalidRobot(String),
+    DuplicateRobot(String),
+    UnknownResource(String),
+    TooManyResources,
+    /// Number of a blueprint the input doesn't have, counted from 1
+    NoBlueprint(usize),
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-struct GeodeCost {
-    ore: usize,
-    obsidian: usize,
-}
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-struct Blueprint {
-    ore_cost: usize,
-    clay_cost: usize,
-    obsidian_cost: ObsidianCost,
-    geode_cost: GeodeCost,
+impl Display for BlueprintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlueprintError::InvalidRobot(sentence) => {
+                write!(f, "can't read robot from \"{}\"", sentence)
+            }
+            BlueprintError::DuplicateRobot(robot) => {
+                write!(f, "{} robot is defined more than once", robot)
+            }
+            BlueprintError::UnknownResource(resource) => {
+                write!(f, "no robot or cost uses resource \"{}\"", resource)
+            }
+            BlueprintError::TooManyResources => {
+                write!(f, "blueprints use at most {} resources", MAX_RESOURCES)
+            }
+            BlueprintError::NoBlueprint(blueprint) => {
+                write!(f, "there's no blueprint {}", blueprint)
+            }
+        }
+    }
 }
 
-fn parse_blueprint(input: &str) -> Blueprint {
-    let mut tokens = input.split("costs ").skip(1);
+/// Amount of every resource, indexed like `Blueprint::resources`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+struct Items([usize; MAX_RESOURCES]);
 
-    let (ore_cost, _) = tokens.next().unwrap().split_once(' ').unwrap();
-    let ore_cost = ore_cost.parse().unwrap();
+impl Items {
+    fn unit(resource: usize) -> Items {
+        let mut items = Items::default();
+        items.0[resource] = 1;
+        items
+    }
 
-    let (clay_cost, _) = tokens.next().unwrap().split_once(' ').unwrap();
-    let clay_cost = clay_cost.parse().unwrap();
+    fn covers(&self, cost: &Items) -> bool {
+        self.0.iter().zip(cost.0).all(|(&have, need)| have >= need)
+    }
 
-    let obsidian_cost = {
-        let mut obsidian_costs = tokens.next().unwrap().split_ascii_whitespace();
-        let ore = obsidian_costs.next().unwrap().parse().unwrap();
-        let clay = obsidian_costs.nth(2).unwrap().parse().unwrap();
-        ObsidianCost { ore, clay }
-    };
+    fn spend(&self, cost: &Items) -> Items {
+        let mut result = *self;
+        for (have, need) in result.0.iter_mut().zip(cost.0) {
+            *have -= need;
+        }
+        result
+    }
+}
 
-    let geode_cost = {
-        let mut geode_costs = tokens.next().unwrap().split_ascii_whitespace();
-        let ore = geode_costs.next().unwrap().parse().unwrap();
-        let obsidian = geode_costs.nth(2).unwrap().parse().unwrap();
-        GeodeCost { ore, obsidian }
-    };
+impl Add for Items {
+    type Output = Self;
 
-    Blueprint {
-        ore_cost,
-        clay_cost,
-        obsidian_cost,
-        geode_cost,
+    fn add(self, rhs: Self) -> Self::Output {
+        let mut result = self;
+        for (item, extra) in result.0.iter_mut().zip(rhs.0) {
+            *item += extra;
+        }
+        result
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-enum Action {
-    Ore,
-    Clay,
-    Obsidian,
-    Geode,
+/// Robot factory where every robot collects one of its resource per minute
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Blueprint {
+    resources: Vec<String>,
+    /// Cost of the robot collecting each resource, `None` when there's no such robot
+    costs: Vec<Option<Items>>,
+    /// Resource to end up with as much of as possible
+    target: usize,
+    /// Robots in the factory before the first minute
+    robots: Items,
 }
 
-enum Possibility {
-    Yes,
-    Wait,
-    No,
+fn resource_index(resources: &mut Vec<String>, name: &str) -> Result<usize, BlueprintError> {
+    if let Some(idx) = resources.iter().position(|resource| resource == name) {
+        return Ok(idx);
+    }
+    if resources.len() == MAX_RESOURCES {
+        return Err(BlueprintError::TooManyResources);
+    }
+    resources.push(name.to_string());
+    Ok(resources.len() - 1)
 }
 
-impl Action {
-    fn is_possible(&self, state: &State, blueprint: &Blueprint) -> Possibility {
-        match self {
-            Action::Ore => {
-                if state.bag.ore >= blueprint.ore_cost {
-                    Possibility::Yes
-                } else {
-                    Possibility::Wait
-                }
-            }
-            Action::Clay => {
-                if state.bag.ore >= blueprint.clay_cost {
-                    Possibility::Yes
-                } else {
-                    Possibility::Wait
-                }
-            }
-            Action::Obsidian => {
-                if state.bag.ore >= blueprint.obsidian_cost.ore
-                    && state.bag.clay >= blueprint.obsidian_cost.clay
-                {
-                    Possibility::Yes
-                } else if state.factory.clay > 0 {
-                    Possibility::Wait
-                } else {
-                    Possibility::No
-                }
-            }
-            Action::Geode => {
-                if state.bag.ore >= blueprint.geode_cost.ore
-                    && state.bag.obsidian >= blueprint.geode_cost.obsidian
-                {
-                    Possibility::Yes
-                } else if state.factory.obsidian > 0 {
-                    Possibility::Wait
-                } else {
-                    Possibility::No
-                }
-            }
-        }
+fn parse_robot(
+    sentence: &str,
+    resources: &mut Vec<String>,
+) -> Result<(usize, Items), BlueprintError> {
+    let invalid = || BlueprintError::InvalidRobot(sentence.to_string());
+    let (robot, costs) = sentence
+        .strip_prefix("Each ")
+        .and_then(|rest| rest.split_once(" robot costs "))
+        .ok_or_else(invalid)?;
+
+    let robot = resource_index(resources, robot)?;
+    let mut cost = Items::default();
+    for part in costs.split(" and ") {
+        let (amount, resource) = part.split_once(' ').ok_or_else(invalid)?;
+        let amount: usize = amount.parse().map_err(|_| invalid())?;
+        cost.0[resource_index(resources, resource)?] += amount;
     }
 
-    fn should_build(&self, state: &State, blueprint: &Blueprint) -> bool {
-        let ore = [
-            blueprint.ore_cost,
-            blueprint.clay_cost,
-            blueprint.obsidian_cost.ore,
-            blueprint.geode_cost.ore,
-        ]
-        .into_iter()
-        .max()
-        .unwrap();
-        let clay = blueprint.obsidian_cost.clay;
-        let obsidian = blueprint.geode_cost.obsidian;
+    Ok((robot, cost))
+}
 
-        match self {
-            Action::Ore => state.factory.ore < ore,
-            Action::Clay => state.factory.clay < clay,
-            Action::Obsidian => state.factory.obsidian < obsidian,
-            Action::Geode => true,
+impl Blueprint {
+    /// Reads robot sentences like the puzzle's, with any resource names. The factory starts
+    /// with one `starting` robot and maximizes `target`
+    pub fn parse(line: &str, target: &str, starting: &str) -> Result<Blueprint, BlueprintError> {
+        let robots = match line.split_once(':') {
+            Some((_, robots)) => robots,
+            None => line,
+        };
+
+        let mut resources = Vec::new();
+        let mut costs = vec![None; MAX_RESOURCES];
+        for sentence in robots.split('.').map(str::trim).filter(|s| !s.is_empty()) {
+            let (robot, cost) = parse_robot(sentence, &mut resources)?;
+            if costs[robot].replace(cost).is_some() {
+                return Err(BlueprintError::DuplicateRobot(resources[robot].clone()));
+            }
         }
+        costs.truncate(resources.len());
+
+        let find = |name: &str| {
+            resources
+                .iter()
+                .position(|resource| resource == name)
+                .ok_or_else(|| BlueprintError::UnknownResource(name.to_string()))
+        };
+        let target = find(target)?;
+        let robots = Items::unit(find(starting)?);
+
+        Ok(Blueprint {
+            resources,
+            costs,
+            target,
+            robots,
+        })
     }
 
-    fn as_items(&self) -> Items {
-        match self {
-            Action::Ore => Items {
-                ore: 1,
-                ..Default::default()
-            },
-            Action::Clay => Items {
-                clay: 1,
-                ..Default::default()
-            },
-            Action::Obsidian => Items {
-                obsidian: 1,
-                ..Default::default()
-            },
-            Action::Geode => Items {
-                geode: 1,
-                ..Default::default()
-            },
-        }
+    /// A puzzle blueprint, maximizing geodes with one ore robot to start with
+    fn puzzle(line: &str) -> Blueprint {
+        Blueprint::parse(line, "geode", "ore").unwrap()
     }
 
-    fn cost(&self, bag: &Items, blueprint: &Blueprint) -> Items {
-        let mut result = *bag;
-        match self {
-            Action::Ore => result.ore -= blueprint.ore_cost,
-            Action::Clay => result.ore -= blueprint.clay_cost,
-            Action::Obsidian => {
-                result.ore -= blueprint.obsidian_cost.ore;
-                result.clay -= blueprint.obsidian_cost.clay;
+    /// Most of each resource spendable in a minute, as robots beyond that never help
+    fn max_spend(&self) -> Items {
+        let mut result = Items::default();
+        for cost in self.costs.iter().flatten() {
+            for (max, &amount) in result.0.iter_mut().zip(&cost.0) {
+                *max = (*max).max(amount);
             }
-            Action::Geode => {
-                result.ore -= blueprint.geode_cost.ore;
-                result.obsidian -= blueprint.geode_cost.obsidian;
-            }
-        };
+        }
         result
     }
-}
 
-const ACTIONS: [Action; 4] = [Action::Geode, Action::Ore, Action::Clay, Action::Obsidian];
-
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
-struct Items {
-    ore: usize,
-    clay: usize,
-    obsidian: usize,
-    geode: usize,
-}
+    /// Robots to try building, the target first
+    fn build_order(&self) -> Vec<usize> {
+        let others = (0..self.costs.len()).filter(|&robot| robot != self.target);
+        std::iter::once(self.target)
+            .chain(others)
+            .filter(|&robot| self.costs[robot].is_some())
+            .collect()
+    }
 
-impl Add for Items {
-    type Output = Self;
+    /// Minutes of collecting before `robot` is affordable, `None` if it never will be
+    fn wait(&self, robot: usize, state: &State) -> Option<usize> {
+        let cost = self.costs[robot].as_ref().unwrap();
+        let mut wait = 0;
+        for resource in 0..self.resources.len() {
+            let missing = cost.0[resource].saturating_sub(state.bag.0[resource]);
+            if missing > 0 {
+                let robots = state.factory.0[resource];
+                if robots == 0 {
+                    return None;
+                }
+                wait = wait.max(missing.div_ceil(robots));
+            }
+        }
+        Some(wait)
+    }
 
-    fn add(self, rhs: Self) -> Self::Output {
-        Self {
-            ore: self.ore + rhs.ore,
-            clay: self.clay + rhs.clay,
-            obsidian: self.obsidian + rhs.obsidian,
-            geode: self.geode + rhs.geode,
+    /// State before the first minute
+    fn start(&self) -> State {
+        State {
+            factory: self.robots,
+            ..Default::default()
         }
     }
+
+    fn should_build(&self, robot: usize, state: &State, max_spend: &Items) -> bool {
+        robot == self.target || state.factory.0[robot] < max_spend.0[robot]
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 struct State {
     turns: usize,
     bag: Items,
     factory: Items,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other
-            .turns
-            .cmp(&self.turns)
-            .then_with(|| self.bag.geode.cmp(&other.bag.geode))
-    }
-}
-
-impl PartialOrd for State {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
 impl State {
     fn pass_minute(&self) -> Self {
-        let mut result = self.clone();
+        let mut result = *self;
         result.bag = result.bag + result.factory;
         result.turns += 1;
 
         result
     }
 
-    fn apply(&self, action: &Action, blueprint: &Blueprint) -> Self {
-        let factory = self.factory + action.as_items();
-        let bag = action.cost(&self.bag, blueprint) + self.factory;
+    fn apply(&self, robot: usize, blueprint: &Blueprint) -> Self {
+        let cost = blueprint.costs[robot].as_ref().unwrap();
+        let factory = self.factory + Items::unit(robot);
+        let bag = self.bag.spend(cost) + self.factory;
 
         State {
             factory,
@@ -240,97 +244,441 @@ impl State {
     }
 }
 
-fn maximize_geodes(blueprint: Blueprint, time: usize) -> usize {
-    let mut heap = BinaryHeap::new();
-    let mut seen = HashSet::new();
-    let mut start = State::default();
-    start.factory.ore = 1;
+/// Robots an optimal plan starts building, minute by minute
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Schedule<'a> {
+    blueprint: &'a Blueprint,
+    /// Robot started in each minute, if any
+    builds: Vec<Option<usize>>,
+}
 
-    seen.insert((start.bag, start.factory));
-    heap.push(start);
+impl Schedule<'_> {
+    /// State at the end of every minute
+    fn states(&self) -> impl Iterator<Item = State> + '_ {
+        let start = self.blueprint.start();
+        self.builds.iter().scan(start, |state, build| {
+            *state = match build {
+                Some(robot) => state.apply(*robot, self.blueprint),
+                None => state.pass_minute(),
+            };
+            Some(*state)
+        })
+    }
 
-    while let Some(current) = heap.pop() {
-        if current.turns == time {
-            return current.bag.geode;
-        }
+    /// Amount of the target resource at the end
+    pub fn collected(&self) -> usize {
+        self.states()
+            .last()
+            .map_or(0, |state| state.bag.0[self.blueprint.target])
+    }
+}
 
-        for action in ACTIONS.iter() {
-            match (
-                action.is_possible(&current, &blueprint),
-                action.should_build(&current, &blueprint),
-            ) {
-                (Possibility::Yes, true) => {
-                    let next_state = current.apply(action, &blueprint);
-                    if seen.insert((next_state.bag, next_state.factory)) {
-                        heap.push(next_state);
-                        if action == &Action::Geode {
-                            // we never want to do anything but build a geode, if it's possible
-                            break;
-                        }
-                    }
+impl Display for Schedule<'_> {
+    /// Every minute in the puzzle's words
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let blueprint = self.blueprint;
+        let robot_name = |robot: usize| format!("{}-collecting robot", blueprint.resources[robot]);
+
+        let mut before = blueprint.start();
+        for (state, build) in self.states().zip(&self.builds) {
+            writeln!(f, "== Minute {} ==", state.turns)?;
+
+            if let Some(robot) = *build {
+                let cost = blueprint.costs[robot].as_ref().unwrap();
+                let spent: Vec<String> = (0..blueprint.resources.len())
+                    .filter(|&resource| cost.0[resource] > 0)
+                    .map(|resource| {
+                        format!("{} {}", cost.0[resource], blueprint.resources[resource])
+                    })
+                    .collect();
+                let name = robot_name(robot);
+                let article = match name.chars().next() {
+                    Some('a' | 'e' | 'i' | 'o' | 'u') => "an",
+                    _ => "a",
+                };
+                writeln!(
+                    f,
+                    "Spend {} to start building {} {}.",
+                    spent.join(" and "),
+                    article,
+                    name
+                )?;
+            }
+
+            for (resource, &robots) in before.factory.0.iter().enumerate() {
+                if robots == 0 {
+                    continue;
                 }
-                (Possibility::Wait, true) => {
-                    let mut next_state = current.clone();
-                    while let Possibility::Wait = action.is_possible(&next_state, &blueprint) {
-                        next_state = next_state.pass_minute();
-                    }
-                    next_state = next_state.apply(action, &blueprint);
-                    if seen.insert((next_state.bag, next_state.factory)) {
-                        heap.push(next_state);
-                    }
+                let (plural, verb) = if robots == 1 { ("", "s") } else { ("s", "") };
+                writeln!(
+                    f,
+                    "{} {}{} collect{} {} {}; you now have {} {}.",
+                    robots,
+                    robot_name(resource),
+                    plural,
+                    verb,
+                    robots,
+                    blueprint.resources[resource],
+                    state.bag.0[resource],
+                    blueprint.resources[resource]
+                )?;
+            }
+
+            if let Some(robot) = *build {
+                writeln!(
+                    f,
+                    "The new {} is ready; you now have {} of them.",
+                    robot_name(robot),
+                    state.factory.0[robot]
+                )?;
+            }
+            writeln!(f)?;
+            before = state;
+        }
+
+        writeln!(
+            f,
+            "{} {} collected",
+            self.collected(),
+            blueprint.resources[blueprint.target]
+        )
+    }
+}
+
+/// Depth first branch and bound over which robot to build next
+struct Search<'a> {
+    blueprint: &'a Blueprint,
+    time: usize,
+    max_spend: Items,
+    build_order: Vec<usize>,
+    /// Robot started in each minute on the way to the current state
+    builds: Vec<Option<usize>>,
+    best: usize,
+    best_builds: Vec<Option<usize>>,
+    /// States whose next robots were tried, and states dropped as their upper bound couldn't beat
+    /// the best plan so far
+    stats: SearchStats,
+}
+
+impl Search<'_> {
+    /// Most of the target `state` could end up with if robots only cost what's in the bag,
+    /// without spending it, and one of every kind could be built each minute
+    fn upper_bound(&self, state: &State) -> usize {
+        let mut bag = state.bag;
+        let mut factory = state.factory;
+        for _ in state.turns..self.time {
+            let mut built = Items::default();
+            for &robot in &self.build_order {
+                if bag.covers(self.blueprint.costs[robot].as_ref().unwrap()) {
+                    built.0[robot] += 1;
                 }
-                _ => { /* do nothing */ }
             }
+            bag = bag + factory;
+            factory = factory + built;
         }
+        bag.0[self.blueprint.target]
     }
 
-    panic!("Unexpected unreachable");
+    fn explore(&mut self, state: State) {
+        let target = self.blueprint.target;
+        let remaining = self.time - state.turns;
+
+        // building nothing more
+        let idle = state.bag.0[target] + state.factory.0[target] * remaining;
+        if idle > self.best {
+            self.best = idle;
+            self.best_builds = self.builds.clone();
+        }
+
+        if self.upper_bound(&state) <= self.best {
+            self.stats.pruned += 1;
+            return;
+        }
+        self.stats.expanded += 1;
+
+        for idx in 0..self.build_order.len() {
+            let robot = self.build_order[idx];
+            if !self.blueprint.should_build(robot, &state, &self.max_spend) {
+                continue;
+            }
+            // a robot done in the last minute never collects anything
+            let wait = match self.blueprint.wait(robot, &state) {
+                Some(wait) if wait + 1 < remaining => wait,
+                _ => continue,
+            };
+
+            let mut next = state;
+            for _ in 0..wait {
+                next = next.pass_minute();
+            }
+            let next = next.apply(robot, self.blueprint);
+
+            self.builds[next.turns - 1] = Some(robot);
+            self.explore(next);
+            self.builds[next.turns - 1] = None;
+        }
+    }
+}
+
+/// Plan collecting as much of the blueprint's target resource as possible in `time` minutes
+fn maximize(blueprint: &Blueprint, time: usize) -> (Schedule<'_>, SearchStats) {
+    let mut search = Search {
+        blueprint,
+        time,
+        max_spend: blueprint.max_spend(),
+        build_order: blueprint.build_order(),
+        builds: vec![None; time],
+        best: 0,
+        best_builds: vec![None; time],
+        stats: SearchStats::default(),
+    };
+    search.explore(blueprint.start());
+
+    let schedule = Schedule {
+        blueprint,
+        builds: search.best_builds,
+    };
+    (schedule, search.stats)
+}
+
+/// Maximizes every blueprint on its own thread
+fn maximize_all(blueprints: &[Blueprint], time: usize) -> Vec<(Schedule<'_>, SearchStats)> {
+    thread::scope(|scope| {
+        let searches: Vec<_> = blueprints
+            .iter()
+            .map(|blueprint| scope.spawn(move || maximize(blueprint, time)))
+            .collect();
+        searches
+            .into_iter()
+            .map(|search| search.join().unwrap())
+            .collect()
+    })
+}
+
+/// How `blueprint` collects the most of its target in `minutes`, minute by minute
+pub fn plan(
+    input: &str,
+    blueprint: usize,
+    minutes: usize,
+    target: &str,
+    starting: &str,
+) -> Result<String, BlueprintError> {
+    let line = blueprint
+        .checked_sub(1)
+        .and_then(|idx| input.lines().nth(idx))
+        .ok_or(BlueprintError::NoBlueprint(blueprint))?;
+    let blueprint = Blueprint::parse(line, target, starting)?;
+
+    let (schedule, stats) = maximize(&blueprint, minutes);
+    Ok(format!("{}search: {}\n", schedule, stats))
+}
+
+/// Most of `target` every blueprint collects in `minutes`, starting with one `starting` robot
+pub fn report(
+    input: &str,
+    minutes: usize,
+    target: &str,
+    starting: &str,
+) -> Result<String, BlueprintError> {
+    let blueprints = input
+        .lines()
+        .map(|line| Blueprint::parse(line, target, starting))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut output = String::new();
+    for (idx, (schedule, stats)) in maximize_all(&blueprints, minutes).iter().enumerate() {
+        output += &format!(
+            "blueprint {}: {} {} ({})\n",
+            idx + 1,
+            schedule.collected(),
+            target,
+            stats
+        );
+    }
+    Ok(output)
 }
 
 impl Solve<usize, usize> for Day19 {
     fn part1(input: &str) -> usize {
-        input
-            .lines()
-            .map(parse_blueprint)
-            .map(|bp| maximize_geodes(bp, 24))
+        let blueprints: Vec<Blueprint> = input.lines().map(Blueprint::puzzle).collect();
+        maximize_all(&blueprints, 24)
+            .iter()
+            .map(|(schedule, _)| schedule.collected())
             .enumerate()
             .map(|(idx, geodes)| (idx + 1) * geodes)
             .sum()
     }
     fn part2(input: &str) -> usize {
-        input
-            .lines()
-            .take(3)
-            .map(parse_blueprint)
-            .map(|bp| maximize_geodes(bp, 32))
+        let blueprints: Vec<Blueprint> = input.lines().take(3).map(Blueprint::puzzle).collect();
+        maximize_all(&blueprints, 32)
+            .iter()
+            .map(|(schedule, _)| schedule.collected())
             .product()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use super::*;
 
     const EXAMPLE: &str = r#"Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
 Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian."#;
 
+    fn items(amounts: &[usize]) -> Items {
+        let mut items = Items::default();
+        items.0[..amounts.len()].copy_from_slice(amounts);
+        items
+    }
+
     #[test]
     fn parses_example() {
         let example1 = EXAMPLE.lines().next().unwrap();
-        let bp = parse_blueprint(example1);
+        let bp = Blueprint::puzzle(example1);
         let expected = Blueprint {
-            ore_cost: 4,
-            clay_cost: 2,
-            obsidian_cost: ObsidianCost { ore: 3, clay: 14 },
-            geode_cost: GeodeCost {
-                ore: 2,
-                obsidian: 7,
-            },
+            resources: vec![
+                String::from("ore"),
+                String::from("clay"),
+                String::from("obsidian"),
+                String::from("geode"),
+            ],
+            costs: vec![
+                Some(items(&[4])),
+                Some(items(&[2])),
+                Some(items(&[3, 14])),
+                Some(items(&[2, 0, 7])),
+            ],
+            target: 3,
+            robots: items(&[1]),
         };
 
         assert_eq!(bp, expected);
     }
 
+    #[test]
+    fn other_resources() {
+        // resources don't have to be called ore or geode, nor every robot cost its own
+        let bp = Blueprint::parse(
+            "Each gold robot costs 2 gold. Each wood robot costs 3 gold.",
+            "wood",
+            "gold",
+        )
+        .unwrap();
+        assert_eq!(maximize(&bp, 5).0.collected(), 1);
+        assert_eq!(maximize(&bp, 8).0.collected(), 5);
+        assert_eq!(maximize(&bp, 10).0.collected(), 10);
+
+        assert_eq!(
+            Blueprint::parse("Each a robot costs 1 b.", "c", "a"),
+            Err(BlueprintError::UnknownResource(String::from("c")))
+        );
+        assert_eq!(
+            Blueprint::parse("Each a robot costs 1 a. Each a robot costs 2 a.", "a", "a"),
+            Err(BlueprintError::DuplicateRobot(String::from("a")))
+        );
+        assert_eq!(
+            Blueprint::parse("Each a robot costs some a.", "a", "a"),
+            Err(BlueprintError::InvalidRobot(String::from(
+                "Each a robot costs some a"
+            )))
+        );
+    }
+
+    /// Most of the target a blueprint can collect, trying every choice every minute without
+    /// capping robots or pruning anything
+    fn unpruned(
+        blueprint: &Blueprint,
+        time: usize,
+        state: State,
+        memo: &mut HashMap<State, usize>,
+    ) -> usize {
+        if state.turns == time {
+            return state.bag.0[blueprint.target];
+        }
+        if let Some(&best) = memo.get(&state) {
+            return best;
+        }
+
+        let mut best = unpruned(blueprint, time, state.pass_minute(), memo);
+        for (robot, cost) in blueprint.costs.iter().enumerate() {
+            if cost.is_some_and(|cost| state.bag.covers(&cost)) {
+                let next = state.apply(robot, blueprint);
+                best = best.max(unpruned(blueprint, time, next, memo));
+            }
+        }
+        memo.insert(state, best);
+        best
+    }
+
+    #[test]
+    fn pruning_keeps_best() {
+        let gold = "Each gold robot costs 2 gold. Each wood robot costs 3 gold.";
+        let (first, second) = EXAMPLE.split_once('\n').unwrap();
+        let cases = [
+            (first, "geode", "ore", vec![0, 19]),
+            (first, "obsidian", "ore", vec![12, 16]),
+            (second, "obsidian", "ore", vec![12, 15]),
+            (second, "clay", "ore", vec![1, 5, 10, 14]),
+            (gold, "wood", "gold", vec![0, 5, 10, 15]),
+        ];
+
+        for (line, target, starting, times) in cases {
+            let blueprint = Blueprint::parse(line, target, starting).unwrap();
+            for time in times {
+                let expected = unpruned(&blueprint, time, blueprint.start(), &mut HashMap::new());
+                let (schedule, _) = maximize(&blueprint, time);
+                assert_eq!(schedule.collected(), expected);
+            }
+        }
+
+        let (_, stats) = maximize(&Blueprint::puzzle(first), 24);
+        assert!(stats.pruned > 0);
+    }
+
+    #[test]
+    fn example_schedule() {
+        let bp = Blueprint::puzzle(EXAMPLE.lines().next().unwrap());
+        let (schedule, _) = maximize(&bp, 24);
+        let narrative = schedule.to_string();
+
+        assert_eq!(schedule.collected(), 9);
+        assert!(narrative.starts_with(
+            "== Minute 1 ==
+1 ore-collecting robot collects 1 ore; you now have 1 ore.
+
+== Minute 2 ==
+1 ore-collecting robot collects 1 ore; you now have 2 ore.
+
+== Minute 3 ==
+Spend 2 ore to start building a clay-collecting robot.
+1 ore-collecting robot collects 1 ore; you now have 1 ore.
+The new clay-collecting robot is ready; you now have 1 of them.
+"
+        ));
+        assert!(narrative.contains("== Minute 24 =="));
+        assert!(narrative.ends_with("\n9 geode collected\n"));
+    }
+
+    #[test]
+    fn plans_numbered_blueprints() {
+        let second = plan(EXAMPLE, 2, 24, "geode", "ore").unwrap();
+        assert!(second.contains("\n12 geode collected\nsearch: "));
+
+        assert_eq!(
+            plan(EXAMPLE, 0, 24, "geode", "ore"),
+            Err(BlueprintError::NoBlueprint(0))
+        );
+        assert_eq!(
+            plan(EXAMPLE, 3, 24, "geode", "ore"),
+            Err(BlueprintError::NoBlueprint(3))
+        );
+        assert_eq!(
+            plan(EXAMPLE, 1, 24, "geode", "gold"),
+            Err(BlueprintError::UnknownResource(String::from("gold")))
+        );
+    }
+
     #[test]
     fn example_p1() {
         let result = Day19::part1(EXAMPLE);
@@ -342,7 +690,7 @@ Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsid
     #[test]
     fn example_p2() {
         let result = Day19::part2(EXAMPLE);
-        let expected = 62;
+        let expected = 56 * 62;
 
         assert_eq!(result, expected);
     }
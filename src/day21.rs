@@ -1,15 +1,176 @@
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::runner::Solve;
 
 pub struct Day21;
 
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum MathError {
+    Overflow,
+    DivisionByZero,
+    NotAnInteger(Rational),
+    UnknownMonkey(String),
+    /// humn appears more than once, which inverting the operations can't solve
+    NotLinear,
+    RootNotOperation,
+}
+
+impl fmt::Display for MathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MathError::Overflow => write!(f, "overflow while doing monkey math"),
+            MathError::DivisionByZero => write!(f, "a monkey divided by zero"),
+            MathError::NotAnInteger(value) => write!(f, "result {} is not an integer", value),
+            MathError::UnknownMonkey(name) => write!(f, "there's no monkey called {}", name),
+            MathError::NotLinear => write!(f, "humn appears more than once"),
+            MathError::RootNotOperation => write!(f, "root is not an operation"),
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Exact fraction, always kept reduced and with a positive denominator
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Rational {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Rational {
+    fn new(numerator: i128, denominator: i128) -> Result<Rational, MathError> {
+        if denominator == 0 {
+            return Err(MathError::DivisionByZero);
+        }
+
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Ok(Rational {
+            numerator: numerator.checked_div(divisor).ok_or(MathError::Overflow)?,
+            denominator: denominator
+                .checked_div(divisor)
+                .ok_or(MathError::Overflow)?,
+        })
+    }
+
+    fn integer(value: i64) -> Rational {
+        Rational {
+            numerator: i128::from(value),
+            denominator: 1,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        self.numerator == 0
+    }
+
+    fn checked_add(self, other: Rational) -> Result<Rational, MathError> {
+        let numerator = self
+            .numerator
+            .checked_mul(other.denominator)
+            .zip(other.numerator.checked_mul(self.denominator))
+            .and_then(|(left, right)| left.checked_add(right));
+        let denominator = self.denominator.checked_mul(other.denominator);
+
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) => Rational::new(numerator, denominator),
+            _ => Err(MathError::Overflow),
+        }
+    }
+
+    fn checked_neg(self) -> Result<Rational, MathError> {
+        Ok(Rational {
+            numerator: self.numerator.checked_neg().ok_or(MathError::Overflow)?,
+            denominator: self.denominator,
+        })
+    }
+
+    fn checked_sub(self, other: Rational) -> Result<Rational, MathError> {
+        self.checked_add(other.checked_neg()?)
+    }
+
+    fn checked_mul(self, other: Rational) -> Result<Rational, MathError> {
+        // cross reduce first so the products stay as small as possible
+        let left = gcd(self.numerator, other.denominator).max(1);
+        let right = gcd(other.numerator, self.denominator).max(1);
+
+        let numerator = (self.numerator / left).checked_mul(other.numerator / right);
+        let denominator = (self.denominator / right).checked_mul(other.denominator / left);
+
+        match (numerator, denominator) {
+            (Some(numerator), Some(denominator)) => Rational::new(numerator, denominator),
+            _ => Err(MathError::Overflow),
+        }
+    }
+
+    fn checked_div(self, other: Rational) -> Result<Rational, MathError> {
+        if other.is_zero() {
+            return Err(MathError::DivisionByZero);
+        }
+        let inverse = Rational::new(other.denominator, other.numerator)?;
+        self.checked_mul(inverse)
+    }
+
+    /// The value as an i64, failing when it's fractional or doesn't fit
+    fn to_integer(self) -> Result<i64, MathError> {
+        if self.denominator != 1 {
+            return Err(MathError::NotAnInteger(self));
+        }
+        i64::try_from(self.numerator).map_err(|_| MathError::Overflow)
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Op {
+    fn apply(self, left: Rational, right: Rational) -> Result<Rational, MathError> {
+        match self {
+            Op::Add => left.checked_add(right),
+            Op::Sub => left.checked_sub(right),
+            Op::Mul => left.checked_mul(right),
+            Op::Div => left.checked_div(right),
+        }
+    }
+}
+
+fn parse_op(input: &str) -> Op {
+    match input {
+        "+" => Op::Add,
+        "-" => Op::Sub,
+        "*" => Op::Mul,
+        "/" => Op::Div,
+        _ => panic!("Unexpected op"),
+    }
+}
+
 #[derive(Clone, Debug)]
 enum Statement {
-    Value(i64),
+    Value(Rational),
     Operation {
         left: String,
-        operation: String,
+        operation: Op,
         right: String,
     },
 }
@@ -20,10 +181,10 @@ fn parse_line(input: &str) -> (String, Statement) {
     let tokens: Vec<_> = rhs.split_ascii_whitespace().collect();
 
     let statement = if tokens.len() == 1 {
-        Statement::Value(tokens[0].parse().unwrap())
+        Statement::Value(Rational::integer(tokens[0].parse().unwrap()))
     } else {
         let left = tokens[0].to_owned();
-        let operation = tokens[1].to_owned();
+        let operation = parse_op(tokens[1]);
         let right = tokens[2].to_owned();
 
         Statement::Operation {
@@ -40,123 +201,268 @@ fn parse_input(input: &str) -> HashMap<String, Statement> {
     input.lines().map(parse_line).collect()
 }
 
-fn value(lhs: &str, map: &mut HashMap<String, Statement>) -> i64 {
-    // avoid recomputing the same value twice by removing it and inserting it with the computed
-    // value worst case scenario the value is removed and inserted right after
-    let value = match map.remove(lhs) {
-        Some(Statement::Value(value)) => value,
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Expr {
+    Constant(Rational),
+    Humn,
+    Operation(Box<Expr>, Op, Box<Expr>),
+}
+
+/// Compiles the monkey `name` into an expression tree, folding every subtree that doesn't depend
+/// on `humn` into a constant. With `humn_unknown` false, humn is treated as its shouted value.
+fn compile(
+    name: &str,
+    map: &HashMap<String, Statement>,
+    humn_unknown: bool,
+) -> Result<Expr, MathError> {
+    if humn_unknown && name == "humn" {
+        return Ok(Expr::Humn);
+    }
+
+    match map.get(name) {
+        Some(Statement::Value(value)) => Ok(Expr::Constant(*value)),
         Some(Statement::Operation {
             left,
             operation,
             right,
         }) => {
-            let left_value = value(&left, map);
-            let right_value = value(&right, map);
+            let left = compile(left, map, humn_unknown)?;
+            let right = compile(right, map, humn_unknown)?;
 
-            let value = match operation.as_str() {
-                "+" => left_value + right_value,
-                "-" => left_value - right_value,
-                "*" => left_value * right_value,
-                "/" => left_value / right_value,
-                _ => panic!("Unexpected op"),
-            };
+            Ok(match (left, right) {
+                (Expr::Constant(l), Expr::Constant(r)) => Expr::Constant(operation.apply(l, r)?),
+                (left, right) => Expr::Operation(Box::new(left), *operation, Box::new(right)),
+            })
+        }
+        None => Err(MathError::UnknownMonkey(name.to_string())),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Solution {
+    None,
+    Unique(i64),
+    /// Root is equal no matter what humn shouts
+    Any,
+}
+
+impl fmt::Display for Solution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Solution::None => write!(f, "no integer value of humn makes root equal"),
+            Solution::Unique(value) => write!(f, "humn = {}", value),
+            Solution::Any => write!(f, "any value of humn makes root equal"),
+        }
+    }
+}
+
+/// Undoes each operation between the root of `expr` and humn, computing the exact value the
+/// subtree has to evaluate to until it reaches humn itself.
+fn invert(expr: &Expr, target: Rational) -> Result<Solution, MathError> {
+    let (unknown, op, constant, unknown_on_left) = match expr {
+        Expr::Humn => {
+            return match target.to_integer() {
+                Ok(value) => Ok(Solution::Unique(value)),
+                Err(MathError::NotAnInteger(_)) => Ok(Solution::None),
+                Err(e) => Err(e),
+            }
+        }
+        Expr::Operation(left, op, right) => match (left.as_ref(), right.as_ref()) {
+            (unknown, Expr::Constant(c)) => (unknown, *op, *c, true),
+            (Expr::Constant(c), unknown) => (unknown, *op, *c, false),
+            _ => return Err(MathError::NotLinear),
+        },
+        // constants are folded while compiling, so only a subtree with humn gets here
+        Expr::Constant(_) => return Err(MathError::NotLinear),
+    };
 
-            value
+    let next_target = match (op, unknown_on_left) {
+        (Op::Add, _) => target.checked_sub(constant)?,
+        (Op::Sub, true) => target.checked_add(constant)?,
+        (Op::Sub, false) => constant.checked_sub(target)?,
+        (Op::Mul, _) if constant.is_zero() => {
+            return Ok(if target.is_zero() {
+                Solution::Any
+            } else {
+                Solution::None
+            })
+        }
+        (Op::Mul, _) => target.checked_div(constant)?,
+        (Op::Div, true) if constant.is_zero() => return Err(MathError::DivisionByZero),
+        (Op::Div, true) => target.checked_mul(constant)?,
+        // c / x can never be zero unless c is, and then x can be anything but zero
+        (Op::Div, false) if target.is_zero() => {
+            return Ok(if constant.is_zero() {
+                Solution::Any
+            } else {
+                Solution::None
+            })
         }
-        _ => panic!("Unexpected couldn't find lhs in map"),
+        (Op::Div, false) => constant.checked_div(target)?,
     };
 
-    map.insert(lhs.to_owned(), Statement::Value(value));
-    value
+    invert(unknown, next_target)
 }
 
-fn compare(input_value: i64, original: &HashMap<String, Statement>) -> i64 {
-    let mut map = original.clone();
-    let root = map.remove("root").unwrap();
-    if let Statement::Operation {
-        left,
-        operation: _,
-        right,
-    } = root
-    {
-        map.insert(String::from("humn"), Statement::Value(input_value));
-        let left_value = value(&left, &mut map);
-        let right_value = value(&right, &mut map);
+/// Finds the value humn has to shout so that both sides of root are equal.
+fn solve_humn(map: &HashMap<String, Statement>) -> Result<Solution, MathError> {
+    let (left, right) = match map.get("root") {
+        Some(Statement::Operation { left, right, .. }) => (left, right),
+        Some(_) => return Err(MathError::RootNotOperation),
+        None => return Err(MathError::UnknownMonkey(String::from("root"))),
+    };
 
-        left_value - right_value
-    } else {
-        panic!("Root is not an operation value");
+    let left = compile(left, map, true)?;
+    let right = compile(right, map, true)?;
+
+    match (&left, &right) {
+        (Expr::Constant(l), Expr::Constant(r)) if l == r => Ok(Solution::Any),
+        (Expr::Constant(_), Expr::Constant(_)) => Ok(Solution::None),
+        (unknown, Expr::Constant(value)) | (Expr::Constant(value), unknown) => {
+            invert(unknown, *value)
+        }
+        _ => Err(MathError::NotLinear),
     }
 }
 
-/// Bisect a root value.
-///
-/// Left and right must have different signs, panics otherwise.
-fn bisection_search(
-    map: &HashMap<String, Statement>,
-    mut left: i64,
-    mut right: i64,
-) -> Option<i64> {
-    let left_sign = compare(left, map).signum();
-    let right_sign = compare(right, map).signum();
-    assert_ne!(left_sign, right_sign);
+fn evaluate_root(map: &HashMap<String, Statement>) -> Result<i64, MathError> {
+    match compile("root", map, false)? {
+        Expr::Constant(value) => value.to_integer(),
+        _ => unreachable!("Every monkey is known when humn isn't"),
+    }
+}
 
-    while left < right {
-        let right_value = compare(right, map);
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+        }
+    }
 
-        let mid = (left + right) / 2;
-        let mid_value = compare(mid, map);
-        if mid_value == 0 {
-            return Some(mid);
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Add | Op::Sub => 1,
+            Op::Mul | Op::Div => 2,
         }
+    }
+}
 
-        if right_value.signum() == mid_value.signum() {
-            right = mid;
+impl Expr {
+    /// Writes `self` as an operand of `parent`, only adding the parentheses needed to keep the
+    /// same evaluation order.
+    fn fmt_operand(&self, f: &mut fmt::Formatter<'_>, parent: Op, is_right: bool) -> fmt::Result {
+        let needs_parentheses = match self {
+            Expr::Operation(_, op, _) => {
+                op.precedence() < parent.precedence()
+                    || (is_right
+                        && op.precedence() == parent.precedence()
+                        && matches!(parent, Op::Sub | Op::Div))
+            }
+            Expr::Constant(value) => value.denominator != 1 || value.numerator < 0,
+            Expr::Humn => false,
+        };
+
+        if needs_parentheses {
+            write!(f, "({})", self)
         } else {
-            left = mid;
+            write!(f, "{}", self)
         }
     }
+}
 
-    None
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Constant(value) => write!(f, "{}", value),
+            Expr::Humn => write!(f, "x"),
+            Expr::Operation(left, op, right) => {
+                left.fmt_operand(f, *op, false)?;
+                write!(f, " {} ", op.symbol())?;
+                right.fmt_operand(f, *op, true)
+            }
+        }
+    }
 }
 
-fn bisect(map: &HashMap<String, Statement>) -> i64 {
-    // not sure the right way to calculate the step value.
-    // we need the lowest number that achieves this zero, I think. It's not specified.
-    // I found more values that result in zero.
-    let intervals: Vec<_> = (0..i64::MAX)
-        .step_by(i64::MAX as usize / 10000000)
-        .collect();
+/// What humn has to shout for root to be equal, left for the caller to handle when there's no
+/// single answer.
+pub fn humn(input: &str) -> Result<Solution, String> {
+    solve_humn(&parse_input(input)).map_err(|e| format!("failed to solve for humn: {}", e))
+}
 
-    for interval in intervals.windows(2) {
-        let left = interval[0];
-        let left_value = compare(left, map);
-        let right = interval[1];
-        let right_value = compare(right, map);
+/// Renders monkey `name` as an infix expression of humn, shown as x.
+pub fn expression(input: &str, name: &str) -> Result<String, String> {
+    let map = parse_input(input);
+    let expr = compile(name, &map, true).map_err(|e| compile_error(name, e))?;
 
-        // A zero may exist in this interval if the signs don't match
-        // Because we're dealing with integer values, the zero may not exist
-        // Due to a jump from -1 to 1, for example.
-        if left_value.signum() != right_value.signum() {
-            if let Some(idx) = bisection_search(map, left, right) {
-                return idx;
+    Ok(format!("{} = {}\n", name, expr))
+}
+
+fn compile_error(name: &str, error: MathError) -> String {
+    format!("failed to compile {}: {}", name, error)
+}
+
+/// Renders the equation root checks for part 2, with humn shown as x.
+pub fn humn_equation(input: &str) -> Result<String, String> {
+    let map = parse_input(input);
+    let (left, right) = match map.get("root") {
+        Some(Statement::Operation { left, right, .. }) => (left, right),
+        _ => return Err(String::from("root is not an operation")),
+    };
+    let compile_side = |name: &str| compile(name, &map, true).map_err(|e| compile_error(name, e));
+
+    Ok(format!(
+        "{} = {}\n",
+        compile_side(left)?,
+        compile_side(right)?
+    ))
+}
+
+fn dot_node(expr: &Expr, result: &mut String, next_id: &mut usize) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    match expr {
+        Expr::Constant(value) => *result += &format!("    {} [label=\"{}\"];\n", id, value),
+        Expr::Humn => *result += &format!("    {} [label=\"humn\", shape=box];\n", id),
+        Expr::Operation(left, op, right) => {
+            *result += &format!("    {} [label=\"{}\", shape=circle];\n", id, op.symbol());
+            for child in [left, right] {
+                let child_id = dot_node(child, result, next_id);
+                *result += &format!("    {} -> {};\n", id, child_id);
             }
         }
     }
-    panic!("Couldnt find the zero");
+
+    id
 }
+
+/// Graphviz DOT of the expression tree for monkey `name`, with constant subtrees folded.
+pub fn dot(input: &str, name: &str) -> Result<String, String> {
+    let map = parse_input(input);
+    let expr = compile(name, &map, true).map_err(|e| compile_error(name, e))?;
+
+    let mut result = String::from("digraph monkeys {\n");
+    dot_node(&expr, &mut result, &mut 0);
+    result += "}\n";
+
+    Ok(result)
+}
+
 impl Solve<i64, i64> for Day21 {
     fn part1(input: &str) -> i64 {
-        let mut map = parse_input(input);
-        value("root", &mut map)
+        let map = parse_input(input);
+        evaluate_root(&map).unwrap_or_else(|e| panic!("Failed to evaluate root: {}", e))
     }
     fn part2(input: &str) -> i64 {
-        let map = parse_input(input);
-
-        let value = bisect(&map);
-        assert_eq!(compare(value, &map), 0);
-
-        value
+        match humn(input) {
+            Ok(Solution::Unique(value)) => value,
+            Ok(solution) => panic!("Expected a single answer, but {}", solution),
+            Err(e) => panic!("{}", e),
+        }
     }
 }
 
@@ -195,4 +501,143 @@ hmdt: 32"#;
 
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn solves_example_humn() {
+        let map = parse_input(EXAMPLE);
+
+        assert_eq!(solve_humn(&map), Ok(Solution::Unique(301)));
+    }
+
+    #[test]
+    fn solves_unique_humn() {
+        let map = parse_input("root: sub1 + abcd\nsub1: humn * efgh\nefgh: 2\nabcd: 18\nhumn: 1");
+
+        assert_eq!(solve_humn(&map), Ok(Solution::Unique(9)));
+    }
+
+    #[test]
+    fn reports_no_integer_humn() {
+        let map = parse_input("root: sub1 + abcd\nsub1: humn * efgh\nefgh: 2\nabcd: 7\nhumn: 1");
+
+        assert_eq!(solve_humn(&map), Ok(Solution::None));
+    }
+
+    #[test]
+    fn reports_any_humn() {
+        let map = parse_input("root: sub1 + abcd\nsub1: humn * efgh\nefgh: 0\nabcd: 0\nhumn: 1");
+
+        assert_eq!(solve_humn(&map), Ok(Solution::Any));
+    }
+
+    #[test]
+    fn rejects_unsolvable_humn() {
+        let squared = parse_input("root: aaaa + cccc\naaaa: humn * humn\ncccc: 4\nhumn: 1");
+        assert_eq!(solve_humn(&squared), Err(MathError::NotLinear));
+
+        let both_sides = parse_input("root: aaaa + humn\naaaa: humn - cccc\ncccc: 4\nhumn: 1");
+        assert_eq!(solve_humn(&both_sides), Err(MathError::NotLinear));
+
+        let by_zero =
+            parse_input("root: aaaa + cccc\naaaa: humn / dddd\ndddd: 0\ncccc: 5\nhumn: 1");
+        assert_eq!(solve_humn(&by_zero), Err(MathError::DivisionByZero));
+
+        let number = parse_input("root: 5\nhumn: 1");
+        assert_eq!(solve_humn(&number), Err(MathError::RootNotOperation));
+        assert_eq!(
+            humn("humn: 1"),
+            Err(String::from(
+                "failed to solve for humn: there's no monkey called root"
+            ))
+        );
+    }
+
+    #[test]
+    fn returns_humn_solution() {
+        assert_eq!(humn(EXAMPLE), Ok(Solution::Unique(301)));
+
+        let any = humn("root: sub1 + abcd\nsub1: humn * efgh\nefgh: 0\nabcd: 0\nhumn: 1");
+        assert_eq!(
+            any.map(|solution| solution.to_string()),
+            Ok(String::from("any value of humn makes root equal"))
+        );
+    }
+
+    #[test]
+    fn keeps_intermediate_fractions() {
+        // truncating 3 / 2 would give 2 instead of 3
+        let map = parse_input("root: half * two\nhalf: three / two\nthree: 3\ntwo: 2");
+
+        assert_eq!(evaluate_root(&map), Ok(3));
+    }
+
+    #[test]
+    fn reports_fractional_result() {
+        let map = parse_input("root: one / two\none: 1\ntwo: 2");
+
+        assert_eq!(
+            evaluate_root(&map),
+            Err(MathError::NotAnInteger(Rational::new(1, 2).unwrap()))
+        );
+    }
+
+    #[test]
+    fn reports_overflow() {
+        let big = i64::MAX;
+        let input = format!("root: aaaa * aaaa\naaaa: bbbb * bbbb\nbbbb: {}", big);
+        let map = parse_input(&input);
+
+        assert_eq!(evaluate_root(&map), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn rational_arithmetic() {
+        let third = Rational::new(1, 3).unwrap();
+        let half = Rational::new(-2, -4).unwrap();
+
+        assert_eq!(third.checked_add(half), Rational::new(5, 6));
+        assert_eq!(third.checked_sub(half), Rational::new(-1, 6));
+        assert_eq!(third.checked_mul(half), Rational::new(1, 6));
+        assert_eq!(third.checked_div(half), Rational::new(2, 3));
+        assert_eq!(
+            third.checked_div(Rational::integer(0)),
+            Err(MathError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn prints_example_equation() {
+        assert_eq!(
+            humn_equation(EXAMPLE),
+            Ok(String::from("(4 + 2 * (x - 3)) / 4 = 150\n"))
+        );
+    }
+
+    #[test]
+    fn prints_expression() {
+        assert_eq!(expression(EXAMPLE, "sjmn").unwrap(), "sjmn = 150\n");
+        assert_eq!(expression(EXAMPLE, "ptdq").unwrap(), "ptdq = x - 3\n");
+        assert_eq!(
+            expression(
+                "root: aaaa - bbbb\naaaa: 1\nbbbb: humn - cccc\ncccc: 2\nhumn: 0",
+                "root"
+            )
+            .unwrap(),
+            "root = 1 - (x - 2)\n"
+        );
+        assert_eq!(
+            expression(EXAMPLE, "abcd"),
+            Err(String::from(
+                "failed to compile abcd: there's no monkey called abcd"
+            ))
+        );
+    }
+
+    #[test]
+    fn dot_example() {
+        let result = dot(EXAMPLE, "pppw").unwrap();
+
+        assert_eq!(result.matches(" -> ").count(), 8);
+        assert!(result.contains("[label=\"humn\", shape=box]"));
+    }
 }
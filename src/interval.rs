@@ -0,0 +1,226 @@
+/// Integer types usable as interval bounds
+pub trait Discrete: Copy + Ord {
+    fn checked_next(self) -> Option<Self>;
+    fn checked_prev(self) -> Option<Self>;
+    fn to_i128(self) -> i128;
+}
+
+macro_rules! impl_discrete {
+    ($($t:ty),*) => {
+        $(impl Discrete for $t {
+            fn checked_next(self) -> Option<Self> {
+                self.checked_add(1)
+            }
+
+            fn checked_prev(self) -> Option<Self> {
+                self.checked_sub(1)
+            }
+
+            fn to_i128(self) -> i128 {
+                self as i128
+            }
+        })*
+    };
+}
+
+impl_discrete!(i32, i64, u32, u64, usize);
+
+/// Set of integers stored as sorted, disjoint, inclusive `(start, end)` intervals.
+///
+/// Intervals that overlap or touch are always merged, so two sets holding the same values are
+/// always equal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct IntervalSet<T> {
+    intervals: Vec<(T, T)>,
+}
+
+impl<T: Discrete> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet::new()
+    }
+}
+
+impl<T: Discrete> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet {
+            intervals: Vec::new(),
+        }
+    }
+
+    /// Every value from `start` to `end`, empty when start > end
+    pub fn from_interval(start: T, end: T) -> Self {
+        let intervals = if start <= end {
+            vec![(start, end)]
+        } else {
+            Vec::new()
+        };
+        IntervalSet { intervals }
+    }
+
+    pub fn intervals(&self) -> &[(T, T)] {
+        &self.intervals
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Amount of values in the set, which for the full range of a 64 bit type doesn't fit in a u64
+    pub fn len(&self) -> u128 {
+        self.intervals
+            .iter()
+            .map(|&(start, end)| (end.to_i128() - start.to_i128() + 1) as u128)
+            .sum()
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        self.intervals
+            .iter()
+            .chain(&other.intervals)
+            .copied()
+            .collect()
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut intervals = Vec::new();
+        let (mut left, mut right) = (0, 0);
+
+        while left < self.intervals.len() && right < other.intervals.len() {
+            let (left_start, left_end) = self.intervals[left];
+            let (right_start, right_end) = other.intervals[right];
+
+            let start = left_start.max(right_start);
+            let end = left_end.min(right_end);
+            if start <= end {
+                intervals.push((start, end));
+            }
+
+            if left_end < right_end {
+                left += 1;
+            } else {
+                right += 1;
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+
+    /// Values in `self` that aren't in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        match (self.intervals.first(), self.intervals.last()) {
+            (Some(&(start, _)), Some(&(_, end))) => {
+                self.intersection(&other.complement(start, end))
+            }
+            _ => IntervalSet::new(),
+        }
+    }
+
+    /// Values between `start` and `end` that aren't in the set
+    pub fn complement(&self, start: T, end: T) -> Self {
+        let (first, last) = match (self.intervals.first(), self.intervals.last()) {
+            (Some(&(first, _)), Some(&(_, last))) => (first, last),
+            _ => return IntervalSet::from_interval(start, end),
+        };
+
+        let before = first.checked_prev().map(|prev| (start, prev));
+        let after = last.checked_next().map(|next| (next, end));
+        let intervals = before
+            .into_iter()
+            .chain(self.gaps())
+            .chain(after)
+            .filter_map(|(gap_start, gap_end)| {
+                let gap_start = gap_start.max(start);
+                let gap_end = gap_end.min(end);
+                (gap_start <= gap_end).then_some((gap_start, gap_end))
+            })
+            .collect();
+
+        IntervalSet { intervals }
+    }
+
+    /// The missing intervals between the first and last value of the set
+    pub fn gaps(&self) -> impl Iterator<Item = (T, T)> + '_ {
+        self.intervals.windows(2).filter_map(|window| {
+            // intervals never touch, so both always exist
+            Some((window[0].1.checked_next()?, window[1].0.checked_prev()?))
+        })
+    }
+}
+
+impl<T: Discrete> FromIterator<(T, T)> for IntervalSet<T> {
+    /// Collects any intervals, in any order, skipping empty ones where start > end
+    fn from_iter<I: IntoIterator<Item = (T, T)>>(iter: I) -> Self {
+        let mut all: Vec<(T, T)> = iter
+            .into_iter()
+            .filter(|(start, end)| start <= end)
+            .collect();
+        all.sort();
+
+        let mut intervals: Vec<(T, T)> = Vec::with_capacity(all.len());
+        for (start, end) in all {
+            match intervals.last_mut() {
+                // merge when overlapping or adjacent
+                Some(last) if last.1.checked_next().is_none_or(|next| start <= next) => {
+                    last.1 = last.1.max(end);
+                }
+                _ => intervals.push((start, end)),
+            }
+        }
+
+        IntervalSet { intervals }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(intervals: &[(i32, i32)]) -> IntervalSet<i32> {
+        intervals.iter().copied().collect()
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent() {
+        let result = set(&[(5, 7), (1, 2), (3, 3), (6, 10), (12, 12), (4, 1)]);
+
+        assert_eq!(result.intervals(), &[(1, 3), (5, 10), (12, 12)]);
+        assert_eq!(result.len(), 10);
+    }
+
+    #[test]
+    fn set_operations() {
+        let left = set(&[(0, 10), (20, 30)]);
+        let right = set(&[(5, 25), (40, 41)]);
+
+        assert_eq!(left.union(&right), set(&[(0, 30), (40, 41)]));
+        assert_eq!(left.intersection(&right), set(&[(5, 10), (20, 25)]));
+        assert_eq!(left.difference(&right), set(&[(0, 4), (26, 30)]));
+        assert_eq!(right.difference(&left), set(&[(11, 19), (40, 41)]));
+        assert!(left.difference(&left).is_empty());
+    }
+
+    #[test]
+    fn complement_and_gaps() {
+        let values = set(&[(2, 3), (6, 8), (12, 20)]);
+
+        assert_eq!(values.gaps().collect::<Vec<_>>(), vec![(4, 5), (9, 11)]);
+        assert_eq!(values.complement(0, 15), set(&[(0, 1), (4, 5), (9, 11)]));
+        assert_eq!(values.complement(7, 10), set(&[(9, 10)]));
+        assert_eq!(IntervalSet::new().complement(1, 2), set(&[(1, 2)]));
+    }
+
+    #[test]
+    fn handles_type_bounds() {
+        let values = IntervalSet::from_interval(0u32, 5)
+            .union(&IntervalSet::from_interval(u32::MAX - 1, u32::MAX));
+
+        assert_eq!(
+            values.complement(0, u32::MAX).intervals(),
+            &[(6, u32::MAX - 2)]
+        );
+        assert_eq!(values.len(), 8);
+
+        let everything = IntervalSet::from_interval(i64::MIN, i64::MAX);
+        assert_eq!(everything.len(), 1 << 64);
+    }
+}
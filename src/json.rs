@@ -0,0 +1,359 @@
+use std::fmt::{self, Display, Write};
+
+/// Minimal JSON document, enough to exchange puzzle data with other tools.
+///
+/// Integers that fit in an i64 are kept exact, every other number is a float.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys in the order they appeared
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum JsonErrorKind {
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    InvalidNumber,
+    InvalidEscape,
+}
+
+/// JSON parse failure, `offset` is the byte offset into the document where it happened
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct JsonError {
+    pub offset: usize,
+    pub kind: JsonErrorKind,
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            JsonErrorKind::UnexpectedEnd => write!(f, "unexpected end of json"),
+            JsonErrorKind::UnexpectedChar(ch) => write!(f, "unexpected {:?}", ch),
+            JsonErrorKind::InvalidNumber => write!(f, "invalid number"),
+            JsonErrorKind::InvalidEscape => write!(f, "invalid escape sequence"),
+        }?;
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    offset: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: JsonErrorKind) -> JsonError {
+        JsonError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.offset..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.input[self.offset..];
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    /// Skips whitespace and returns the next char without consuming it
+    fn next_token(&mut self) -> Result<char, JsonError> {
+        self.skip_whitespace();
+        self.peek()
+            .ok_or_else(|| self.error(JsonErrorKind::UnexpectedEnd))
+    }
+
+    fn unexpected(&self, ch: char) -> JsonError {
+        self.error(JsonErrorKind::UnexpectedChar(ch))
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.next_token()? {
+            ch if ch == expected => {
+                self.offset += ch.len_utf8();
+                Ok(())
+            }
+            ch => Err(self.unexpected(ch)),
+        }
+    }
+
+    fn literal(&mut self, text: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in text.chars() {
+            match self.peek() {
+                Some(ch) if ch == expected => self.offset += 1,
+                Some(ch) => return Err(self.unexpected(ch)),
+                None => return Err(self.error(JsonErrorKind::UnexpectedEnd)),
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        match self.next_token()? {
+            '[' => self.array(),
+            '{' => self.object(),
+            '"' => self.string().map(Json::String),
+            'n' => self.literal("null", Json::Null),
+            't' => self.literal("true", Json::Bool(true)),
+            'f' => self.literal("false", Json::Bool(false)),
+            '-' | '0'..='9' => self.number(),
+            ch => Err(self.unexpected(ch)),
+        }
+    }
+
+    /// Parses the items between `open` and `close`, separated by commas
+    fn items<T>(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<T, JsonError>,
+    ) -> Result<Vec<T>, JsonError> {
+        self.expect(open)?;
+        let mut result = Vec::new();
+
+        if self.next_token()? == close {
+            self.offset += 1;
+            return Ok(result);
+        }
+
+        loop {
+            result.push(item(self)?);
+
+            match self.next_token()? {
+                ',' => self.offset += 1,
+                ch if ch == close => {
+                    self.offset += 1;
+                    return Ok(result);
+                }
+                ch => return Err(self.unexpected(ch)),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.items('[', ']', Self::value).map(Json::Array)
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.items('{', '}', |parser| {
+            parser.next_token()?;
+            let key = parser.string()?;
+            parser.expect(':')?;
+            Ok((key, parser.value()?))
+        })
+        .map(Json::Object)
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.offset;
+        let length = self.input[start..]
+            .find(|ch: char| !matches!(ch, '-' | '+' | '.' | 'e' | 'E' | '0'..='9'))
+            .unwrap_or(self.input.len() - start);
+        self.offset += length;
+        let text = &self.input[start..self.offset];
+
+        let invalid = JsonError {
+            offset: start,
+            kind: JsonErrorKind::InvalidNumber,
+        };
+        let digits = text.trim_start_matches('-');
+        let leading_zero =
+            digits.len() > 1 && digits.starts_with('0') && digits.as_bytes()[1].is_ascii_digit();
+        if !digits.starts_with(|ch: char| ch.is_ascii_digit()) || leading_zero {
+            return Err(invalid);
+        }
+
+        if let Ok(value) = text.parse() {
+            Ok(Json::Integer(value))
+        } else {
+            text.parse().map(Json::Float).map_err(|_| invalid)
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32, JsonError> {
+        let hex = self
+            .input
+            .get(self.offset..self.offset + 4)
+            .ok_or_else(|| self.error(JsonErrorKind::InvalidEscape))?;
+        let code =
+            u32::from_str_radix(hex, 16).map_err(|_| self.error(JsonErrorKind::InvalidEscape))?;
+        self.offset += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut result = String::new();
+
+        loop {
+            let ch = self
+                .peek()
+                .ok_or_else(|| self.error(JsonErrorKind::UnexpectedEnd))?;
+            self.offset += ch.len_utf8();
+
+            match ch {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escape_start = self.offset - 1;
+                    let invalid = JsonError {
+                        offset: escape_start,
+                        kind: JsonErrorKind::InvalidEscape,
+                    };
+                    let escaped = self.peek().ok_or(invalid)?;
+                    self.offset += escaped.len_utf8();
+
+                    let unescaped = match escaped {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = self.hex_escape()?;
+                            // characters outside the BMP are written as a surrogate pair
+                            if (0xD800..0xDC00).contains(&code) {
+                                if !self.input[self.offset..].starts_with("\\u") {
+                                    return Err(invalid);
+                                }
+                                self.offset += 2;
+                                let low = self.hex_escape()?;
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(invalid);
+                                }
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+                            char::from_u32(code).ok_or(invalid)?
+                        }
+                        _ => return Err(invalid),
+                    };
+                    result.push(unescaped);
+                }
+                ch if (ch as u32) < 0x20 => {
+                    self.offset -= 1;
+                    return Err(self.unexpected(ch));
+                }
+                ch => result.push(ch),
+            }
+        }
+    }
+}
+
+/// Parses a whole JSON document, only whitespace may surround the value.
+pub fn parse(input: &str) -> Result<Json, JsonError> {
+    let mut parser = Parser { input, offset: 0 };
+    let value = parser.value()?;
+
+    parser.skip_whitespace();
+    match parser.peek() {
+        Some(ch) => Err(parser.unexpected(ch)),
+        None => Ok(value),
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, text: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for ch in text.chars() {
+        match ch {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            ch if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            ch => f.write_char(ch)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Compact serialization, without any whitespace
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Integer(value) => write!(f, "{}", value),
+            Json::Float(value) if value.is_finite() => write!(f, "{:?}", value),
+            Json::Float(_) => write!(f, "null"),
+            Json::String(text) => write_string(f, text),
+            Json::Array(values) => {
+                f.write_char('[')?;
+                for (idx, value) in values.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(entries) => {
+                f.write_char('{')?;
+                for (idx, (key, value)) in entries.iter().enumerate() {
+                    if idx > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_document() {
+        let input =
+            r#" {"packets": [[1, -2], []], "ok": true, "none": null, "pi": 3.5e0, "s": "a\"é😀"} "#;
+
+        let expected = Json::Object(vec![
+            (
+                String::from("packets"),
+                Json::Array(vec![
+                    Json::Array(vec![Json::Integer(1), Json::Integer(-2)]),
+                    Json::Array(vec![]),
+                ]),
+            ),
+            (String::from("ok"), Json::Bool(true)),
+            (String::from("none"), Json::Null),
+            (String::from("pi"), Json::Float(3.5)),
+            (String::from("s"), Json::String(String::from("a\"é😀"))),
+        ]);
+
+        assert_eq!(parse(input), Ok(expected));
+    }
+
+    #[test]
+    fn round_trips() {
+        let input = r#"{"a":[1,2.5,"x\ny",false,null],"b":{}}"#;
+
+        assert_eq!(parse(input).unwrap().to_string(), input);
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = |offset, kind| Err(JsonError { offset, kind });
+
+        assert_eq!(parse("[1,]"), error(3, JsonErrorKind::UnexpectedChar(']')));
+        assert_eq!(parse("[1"), error(2, JsonErrorKind::UnexpectedEnd));
+        assert_eq!(parse("[01]"), error(1, JsonErrorKind::InvalidNumber));
+        assert_eq!(parse(r#""\q""#), error(1, JsonErrorKind::InvalidEscape));
+        assert_eq!(parse("nul"), error(3, JsonErrorKind::UnexpectedEnd));
+        assert_eq!(parse("[] x"), error(3, JsonErrorKind::UnexpectedChar('x')));
+    }
+}
@@ -0,0 +1,3 @@
+//! Helpers from the solutions that are useful outside of the puzzles
+
+pub mod interval;
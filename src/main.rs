@@ -1,6 +1,12 @@
 use std::env;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
 
-use crate::runner::{parse_day, read_day_input, Solve};
+use crate::runner::{
+    parse_arg, parse_day, parse_required_arg, read_day_input, read_file, required_arg, Solve,
+};
+mod json;
 mod runner;
 
 mod day01;
@@ -28,7 +34,7 @@ mod day21;
 fn main() {
     let args: Vec<String> = env::args().collect();
 
-    if args.len() != 2 {
+    if args.len() < 2 {
         eprintln!("error: missing day to run.");
         return;
     }
@@ -42,6 +48,14 @@ fn main() {
     };
     let input = read_day_input(day);
 
+    if let Some(command) = args.get(2) {
+        match run_command(day, command, &args[3..], &input) {
+            Ok(output) => print!("{}", output),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        return;
+    }
+
     let (p1, p2) = match day {
         1 => day01::Day01::solve(&input),
         2 => day02::Day02::solve(&input),
@@ -74,3 +88,163 @@ fn main() {
     println!("Part 1: {}", p1);
     println!("Part 2: {}", p2);
 }
+
+/// Day 17 chamber with the rocks drawn in the file at `path`, or the puzzle's rocks
+fn day17_chamber(
+    path: Option<&String>,
+    width: i32,
+    spawn_left: i32,
+    spawn_above: i32,
+) -> Result<day17::Chamber, String> {
+    let rocks = match path {
+        Some(path) => read_file(path)?,
+        None => String::from(day17::PUZZLE_ROCKS),
+    };
+    day17::Chamber::new(&rocks, width, spawn_left, spawn_above).map_err(|e| e.to_string())
+}
+
+/// Extra tools some days provide on top of solving, ran as `{day} {command} [args..]`
+fn run_command(day: i32, command: &str, args: &[String], input: &str) -> Result<String, String> {
+    let output = match (day, command) {
+        (11, "business") => {
+            let rounds = parse_required_arg(args, 0, "rounds")?;
+            let business = if parse_arg(args, 1, "worry relief", false)? {
+                day11::monkey_business_with_relief(input, rounds)
+            } else {
+                day11::monkey_business_after(input, rounds)?
+            };
+            format!("{}\n", business)
+        }
+        (11, "dot") => day11::dot(input, parse_arg(args, 0, "rounds", 10_000)?)?,
+        (13, "sort") => match args.first() {
+            Some(path) => day13::sort_packets(&read_file(path)?, path.ends_with(".json"))?,
+            None => day13::sort_packets(input, false)?,
+        },
+        (13, "json") => day13::to_json(input)?,
+        (13, "check") => day13::check_json_pairs(&read_file(required_arg(args, 0, "json file")?)?)?,
+        (14, "render") => day14::render(input, parse_arg(args, 0, "part", 2)?),
+        (14, "frames") => {
+            let directory = required_arg(args, 0, "directory")?;
+            let every = parse_arg(args, 1, "frame interval", 100)?;
+            let part = parse_arg(args, 2, "part", 2)?;
+
+            let frames = day14::write_frames(input, part, Path::new(directory), every)
+                .map_err(|e| format!("failed to write frames: {}", e))?;
+            format!("wrote {} frames to {}\n", frames, directory)
+        }
+        (15, "uncovered") => {
+            let coordinate = |idx| -> Result<(i32, i32), String> {
+                Ok((
+                    parse_required_arg(args, idx, "x")?,
+                    parse_required_arg(args, idx + 1, "y")?,
+                ))
+            };
+            day15::uncovered(input, coordinate(0)?, coordinate(2)?)
+        }
+        (16, "plan") => day16::plan(input, parse_arg(args, 0, "part", 2)?)?,
+        (16, "optimize") => {
+            let problem = day16::ValveProblem {
+                agents: parse_required_arg(args, 0, "agents")?,
+                minutes: parse_required_arg(args, 1, "minutes")?,
+                teaching: parse_arg(args, 2, "teaching minutes", 0)?,
+                start: parse_arg(args, 3, "start valve", String::from("AA"))?,
+            };
+            let plan = problem.solve(input)?;
+            format!("{}search: {}\n", plan, plan.search)
+        }
+        (16, "dot") => {
+            let start: String = parse_arg(args, 1, "start valve", String::from("AA"))?;
+            match args.first().map(String::as_str) {
+                None | Some("contracted") => day16::dot(input, true, &start)?,
+                Some("raw") => day16::dot(input, false, &start)?,
+                Some(graph) => {
+                    return Err(format!(
+                        "unknown graph \"{}\", expected raw or contracted",
+                        graph
+                    ))
+                }
+            }
+        }
+        (17, "height") => {
+            let chamber = day17_chamber(
+                args.get(4),
+                parse_arg(args, 1, "width", 7)?,
+                parse_arg(args, 2, "spawn left", 2)?,
+                parse_arg(args, 3, "spawn above", 3)?,
+            )?;
+            let rock_amount = parse_required_arg(args, 0, "rock amount")?;
+            let height = day17::height(input, &chamber, rock_amount).map_err(|e| e.to_string())?;
+            format!("{}\n", height)
+        }
+        (17, "replay") => {
+            let chamber = day17_chamber(args.get(3), 7, 2, 3)?;
+            let delay = match parse_arg(args, 2, "delay in milliseconds", 0)? {
+                0 => None,
+                delay => Some(Duration::from_millis(delay)),
+            };
+            day17::replay(
+                input,
+                &chamber,
+                parse_required_arg(args, 0, "rock amount")?,
+                parse_arg(args, 1, "rows", 20)?,
+                delay,
+                &mut io::stdout(),
+            )
+            .map_err(|e| format!("failed to write replay: {}", e))?;
+            String::new()
+        }
+        (17, "frames") => {
+            let directory = required_arg(args, 0, "directory")?;
+            let chamber = day17_chamber(args.get(3), 7, 2, 3)?;
+
+            let frames = day17::write_frames(
+                input,
+                &chamber,
+                parse_required_arg(args, 1, "rock amount")?,
+                parse_arg(args, 2, "rows", 40)?,
+                Path::new(directory),
+            )
+            .map_err(|e| format!("failed to write frames: {}", e))?;
+            format!("wrote {} frames to {}\n", frames, directory)
+        }
+        (18, "mesh") => match args.first().map(String::as_str) {
+            None | Some("obj") => day18::mesh(input, false),
+            Some("stl") => day18::mesh(input, true),
+            Some(format) => {
+                return Err(format!(
+                    "unknown mesh format \"{}\", expected obj or stl",
+                    format
+                ))
+            }
+        },
+        (18, "pockets") => day18::pockets(input),
+        (19, "maximize") => day19::report(
+            input,
+            parse_arg(args, 0, "minutes", 24)?,
+            parse_arg(args, 1, "target", String::from("geode"))?.as_str(),
+            parse_arg(args, 2, "starting robot", String::from("ore"))?.as_str(),
+        )
+        .map_err(|e| e.to_string())?,
+        (19, "plan") => day19::plan(
+            input,
+            parse_arg(args, 0, "blueprint", 1)?,
+            parse_arg(args, 1, "minutes", 24)?,
+            parse_arg(args, 2, "target", String::from("geode"))?.as_str(),
+            parse_arg(args, 3, "starting robot", String::from("ore"))?.as_str(),
+        )
+        .map_err(|e| e.to_string())?,
+        (21, "expr") => day21::expression(
+            input,
+            parse_arg(args, 0, "monkey", String::from("root"))?.as_str(),
+        )?,
+        (21, "equation") => day21::humn_equation(input)?,
+        (21, "humn") => format!("{}\n", day21::humn(input)?),
+        (21, "dot") => day21::dot(
+            input,
+            parse_arg(args, 0, "monkey", String::from("root"))?.as_str(),
+        )?,
+        _ => return Err(format!("unknown command \"{}\" for day {}", command, day)),
+    };
+
+    Ok(output)
+}
@@ -1,5 +1,6 @@
 use std::fmt::Display;
 use std::num::ParseIntError;
+use std::str::FromStr;
 use std::{fs::File, io::Read};
 
 pub trait Solve<T1, T2>
@@ -18,6 +19,21 @@ where
     }
 }
 
+/// Work done by a search, to compare pruning strategies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchStats {
+    /// Nodes whose children were tried
+    pub expanded: usize,
+    /// Nodes dropped without trying their children
+    pub pruned: usize,
+}
+
+impl Display for SearchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} expanded, {} pruned", self.expanded, self.pruned)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum DayError {
     NotADay,
@@ -50,6 +66,44 @@ pub fn parse_day(day_str: &str) -> Result<i32, DayError> {
     }
 }
 
+/// Parses the optional command argument at `idx`, falling back to `default` when it's missing.
+pub fn parse_arg<T>(args: &[String], idx: usize, name: &str, default: T) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match args.get(idx) {
+        Some(arg) => arg
+            .parse()
+            .map_err(|e| format!("failed to parse {}: {}", name, e)),
+        None => Ok(default),
+    }
+}
+
+pub fn required_arg<'a>(args: &'a [String], idx: usize, name: &str) -> Result<&'a str, String> {
+    args.get(idx)
+        .map(String::as_str)
+        .ok_or_else(|| format!("missing {}", name))
+}
+
+pub fn parse_required_arg<T>(args: &[String], idx: usize, name: &str) -> Result<T, String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    required_arg(args, idx, name)?
+        .parse()
+        .map_err(|e| format!("failed to parse {}: {}", name, e))
+}
+
+pub fn read_file(path: &str) -> Result<String, String> {
+    let mut contents = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|e| format!("failed to read \"{}\": {}", path, e))?;
+    Ok(contents)
+}
+
 pub fn read_day_input(day: i32) -> String {
     let filename = format!("./input/{:02}.input", day);
     let mut file = File::open(&filename)